@@ -0,0 +1,125 @@
+use futures_util::future::BoxFuture;
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch;
+
+/// 当前时间的来源
+///
+/// 限速的重置和等待、klines的完成检查以及签名请求的timestamp都从Clock获取当前时间，
+/// 默认为`SystemClock`，测试时可以使用`ManualClock`手动推进时间，
+/// 在几毫秒内模拟数小时的请求
+pub trait Clock: Debug + Send + Sync {
+    /// 当前的epoch微秒
+    fn now_micros(&self) -> i64;
+
+    /// 等待一段时间
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// 当前的epoch毫秒
+    fn now_millis(&self) -> i64 {
+        self.now_micros().div_euclid(1000)
+    }
+
+    fn system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_micros(self.now_micros().max(0) as u64)
+    }
+}
+
+/// 系统时间
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_micros(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_micros() as i64
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// 手动推进的时钟，所有克隆共享同一个时间
+///
+/// 时间只在调用`advance()`或`set()`时改变，`sleep()`在时间被推进到目标时间后返回
+///
+/// ```rust
+/// let clock = ManualClock::new(1_700_000_000_000);
+/// let conn = RestConn::builder().clock(clock.clone()).build().await?;
+/// clock.advance(Duration::from_secs(3600));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    /// 当前的epoch微秒
+    now: Arc<watch::Sender<i64>>,
+}
+
+impl ManualClock {
+    /// 从给定的epoch毫秒开始
+    pub fn new(now_millis: i64) -> Self {
+        Self {
+            now: Arc::new(watch::channel(now_millis * 1000).0),
+        }
+    }
+
+    /// 将时间向后推进
+    pub fn advance(&self, duration: Duration) {
+        self.now.send_modify(|x| *x += duration.as_micros() as i64);
+    }
+
+    /// 将时间设置为给定的epoch毫秒，不能早于当前时间
+    pub fn set(&self, now_millis: i64) {
+        self.now.send_modify(|x| *x = (*x).max(now_millis * 1000));
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_micros(&self) -> i64 {
+        *self.now.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let mut rx = self.now.subscribe();
+        let until = *rx.borrow() + duration.as_micros() as i64;
+        Box::pin(async move {
+            // 发送端被drop时，时间不会再推进，永远等待
+            if rx.wait_for(|x| *x >= until).await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_manual_clock() {
+        let clock = ManualClock::new(1_700_000_000_000);
+        assert_eq!(clock.now_millis(), 1_700_000_000_000);
+
+        let sleep = tokio::spawn(clock.sleep(Duration::from_secs(3600)));
+        clock.advance(Duration::from_secs(1800));
+        tokio::task::yield_now().await;
+        assert!(!sleep.is_finished());
+
+        clock.advance(Duration::from_secs(1800));
+        sleep.await.unwrap();
+        assert_eq!(clock.now_millis(), 1_700_003_600_000);
+
+        // 不能回拨
+        clock.set(1_600_000_000_000);
+        assert_eq!(clock.now_millis(), 1_700_003_600_000);
+        assert_eq!(
+            clock.system_time(),
+            UNIX_EPOCH + Duration::from_millis(1_700_003_600_000)
+        );
+    }
+}
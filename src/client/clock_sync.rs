@@ -0,0 +1,118 @@
+use super::clock::{Clock, SystemClock};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// 本地时间与服务器时间的同步状态，所有克隆共享
+///
+/// 通过`RestConn::sync_clock()`采样服务器时间，估算本地时间与服务器时间之差，
+/// 签名请求的timestamp将使用校正后的时间，避免本地时钟漂移导致-1021错误
+///
+/// ```rust
+/// // WebSocket API和RestConn共享同一个时钟
+/// ws_client.set_clock_sync(rest_conn.clock_sync());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ClockSync {
+    inner: Arc<ClockSyncInner>,
+}
+
+#[derive(Debug)]
+struct ClockSyncInner {
+    /// 本地时间的来源
+    clock: Arc<dyn Clock>,
+    /// 服务器时间 - 本地时间(毫秒)
+    offset: AtomicI64,
+    /// 最近一次同步时的往返时间(微秒)
+    rtt: AtomicU64,
+    synced: AtomicBool,
+}
+
+impl Default for ClockSyncInner {
+    fn default() -> Self {
+        Self {
+            clock: Arc::new(SystemClock),
+            offset: AtomicI64::default(),
+            rtt: AtomicU64::default(),
+            synced: AtomicBool::default(),
+        }
+    }
+}
+
+impl ClockSync {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 使用给定的时钟作为本地时间
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: Arc::new(ClockSyncInner {
+                clock,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// 本地时间的来源
+    pub fn source(&self) -> &Arc<dyn Clock> {
+        &self.inner.clock
+    }
+
+    /// 服务器时间与本地时间之差(毫秒)，为正表示本地时间落后于服务器时间
+    pub fn offset(&self) -> i64 {
+        self.inner.offset.load(Ordering::Relaxed)
+    }
+
+    /// 最近一次同步时，请求服务器时间的往返时间
+    pub fn rtt(&self) -> Duration {
+        Duration::from_micros(self.inner.rtt.load(Ordering::Relaxed))
+    }
+
+    /// 是否已经同步过
+    pub fn is_synced(&self) -> bool {
+        self.inner.synced.load(Ordering::Relaxed)
+    }
+
+    /// 校正后的当前时间(毫秒)
+    pub fn now(&self) -> u128 {
+        (self.inner.clock.now_millis() + self.offset()).max(0) as u128
+    }
+
+    /// 记录一次采样：发送请求前的本地时间、服务器时间、收到响应后的本地时间(都为毫秒)，
+    /// 假设服务器时间是在往返时间的中点生成的
+    pub(crate) fn record(&self, local_before: u128, server: u64, local_after: u128, rtt: Duration) {
+        let local_mid = ((local_before + local_after) / 2) as i128;
+        let offset = server as i128 - local_mid;
+        self.inner.offset.store(offset as i64, Ordering::Relaxed);
+        self.inner
+            .rtt
+            .store(rtt.as_micros() as u64, Ordering::Relaxed);
+        self.inner.synced.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let clock = ClockSync::new();
+        assert!(!clock.is_synced());
+        assert_eq!(clock.offset(), 0);
+
+        // 本地时间落后服务器1500毫秒，往返时间200毫秒
+        clock.record(10_000, 11_600, 10_200, Duration::from_millis(200));
+        assert!(clock.is_synced());
+        assert_eq!(clock.offset(), 1500);
+        assert_eq!(clock.rtt(), Duration::from_millis(200));
+
+        clock.record(10_000, 9_100, 10_200, Duration::from_millis(200));
+        assert_eq!(clock.offset(), -1000);
+    }
+}
@@ -0,0 +1,60 @@
+use super::{RestMethod, params::Param, rate_limit::RateLimitParam, weight::WeightTable};
+use serde::{Serialize, de::DeserializeOwned};
+use std::fmt::Debug;
+use tracing::warn;
+
+/// 带有类型的REST接口，在Param的基础上指定请求方法、路径、权重和响应类型，
+/// 通过`RestConn::execute()`发送请求并得到解析后的响应
+///
+/// 下游可以为自定义的参数类型实现该Trait，以调用本crate尚未提供的接口：
+///
+/// ```rust
+/// #[derive(Debug, Serialize)]
+/// #[serde(rename_all = "camelCase")]
+/// struct PTickerTradingDay {
+///     symbol: String,
+/// }
+/// impl Param for PTickerTradingDay {}
+/// impl Endpoint for PTickerTradingDay {
+///     type Response = serde_json::Value;
+///     const METHOD: RestMethod = RestMethod::Get;
+///     const PATH: &'static str = "/api/v3/ticker/tradingDay";
+///     fn weight(&self) -> RateLimitParam {
+///         RateLimitParam::Weight(4)
+///     }
+/// }
+///
+/// let ticker = rest_conn.execute(PTickerTradingDay { symbol: "BTCUSDT".into() }).await?;
+/// ```
+pub trait Endpoint: Param + Serialize + Debug {
+    /// 响应体反序列化后的类型
+    type Response: DeserializeOwned;
+    /// 请求方法
+    const METHOD: RestMethod;
+    /// 请求路径，例如"/api/v3/order"
+    const PATH: &'static str;
+    /// 是否幂等(重复执行不会产生副作用)，幂等的请求在超时或5xx时可以安全重试，默认只有GET请求是幂等的
+    const IDEMPOTENT: bool = matches!(Self::METHOD, RestMethod::Get);
+
+    /// 计算权重所依据的参数值，例如depth的limit、ticker的交易对个数，默认为None
+    fn weight_arg(&self) -> Option<u32> {
+        None
+    }
+
+    /// 请求所需的权重，默认根据weight_arg()从内置的权重表计算，
+    /// 下游的接口不在内置的权重表中，需要实现该方法指定权重，否则记录警告并按权重1计算
+    ///
+    /// 发送请求时，`RestConn::set_weight_table()`设置的权重表优先于该方法
+    fn weight(&self) -> RateLimitParam {
+        WeightTable::builtin()
+            .weight(Self::METHOD, Self::PATH, self.weight_arg())
+            .unwrap_or_else(|| {
+                warn!(
+                    "{:?} {} not in the weight table, implement Endpoint::weight() for it, use weight 1",
+                    Self::METHOD,
+                    Self::PATH
+                );
+                RateLimitParam::Weight(1)
+            })
+    }
+}
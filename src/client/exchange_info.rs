@@ -0,0 +1,815 @@
+use super::clock::Clock;
+use crate::{ExchangeInfo, Permission, RateLimit, SymbolInfo, errors::BiAnResult};
+use arc_swap::ArcSwapOption;
+use ba_global::app_dir;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    fs,
+    sync::{Mutex, broadcast},
+};
+use tracing::{info, warn};
+
+/// exchange_info变化事件的通道容量
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// 解析exchangeInfo的响应
+///
+/// rateLimits中ba_types无法解析的限速规则(例如币安新增了HOUR时间段)被忽略并记录日志，
+/// 而不是导致整个响应解析失败，其它的限速规则照常生效
+pub(crate) fn parse_exchange_info(res: &str) -> BiAnResult<ExchangeInfo> {
+    let mut value = serde_json::from_str::<Value>(res)?;
+    if let Some(rate_limits) = value.get_mut("rateLimits").and_then(Value::as_array_mut) {
+        rate_limits.retain(|x| match RateLimit::deserialize(x) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("ignore unsupported rate limit {}: {}", x, e);
+                false
+            }
+        });
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// exchange_info的查询范围，不同的查询范围使用各自独立的缓存
+///
+/// 默认查询所有现货交易对，并只保留报价资产为USDT的交易对
+///
+/// ```rust
+/// // 所有现货和杠杆交易对中，报价资产为USDT、FDUSD、BTC的正在交易的交易对
+/// let query = ExchangeInfoQuery::new(vec![Permission::Spot, Permission::Margin])
+///     .quote_assets(["USDT", "FDUSD", "BTC"])
+///     .symbol_status("TRADING");
+/// // 指定的几个交易对
+/// let query = ExchangeInfoQuery::symbols(["BTCEUR", "ETHBTC"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ExchangeInfoQuery {
+    /// 为空时表示不按权限筛选(只在指定了symbols时为空)
+    pub(crate) permissions: Vec<Permission>,
+    /// 不能与permissions、symbol_status同时使用
+    pub(crate) symbols: Vec<String>,
+    /// TRADING、HALT、BREAK
+    pub(crate) symbol_status: Option<String>,
+    /// 只保留这些报价资产的交易对，为空时保留所有交易对
+    pub(crate) quote_assets: Vec<String>,
+}
+
+impl Default for ExchangeInfoQuery {
+    fn default() -> Self {
+        Self::new(vec![Permission::Spot]).quote_assets(["USDT"])
+    }
+}
+
+impl ExchangeInfoQuery {
+    /// 查询具有给定权限的所有交易对
+    pub fn new(permissions: Vec<Permission>) -> Self {
+        Self {
+            permissions,
+            symbols: vec![],
+            symbol_status: None,
+            quote_assets: vec![],
+        }
+    }
+
+    /// 只查询给定的交易对
+    pub fn symbols<I, S>(symbols: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            permissions: vec![],
+            symbols: symbols
+                .into_iter()
+                .map(|s| s.as_ref().to_uppercase())
+                .collect(),
+            symbol_status: None,
+            quote_assets: vec![],
+        }
+    }
+
+    /// 只查询处于给定状态的交易对，例如"TRADING"
+    pub fn symbol_status(mut self, status: &str) -> Self {
+        self.symbol_status = Some(status.to_uppercase());
+        self
+    }
+
+    /// 只保留报价资产为给定资产的交易对
+    pub fn quote_assets<I, S>(mut self, quote_assets: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.quote_assets = quote_assets
+            .into_iter()
+            .map(|s| s.as_ref().to_uppercase())
+            .collect();
+        self
+    }
+
+    /// 权限的字符串形式，例如"SPOT"
+    pub(crate) fn permission_strs(&self) -> Vec<String> {
+        self.permissions.iter().map(to_plain_string).collect()
+    }
+
+    /// 用于区分不同查询范围的缓存
+    pub(crate) fn cache_key(&self) -> String {
+        let mut permissions = self.permission_strs();
+        permissions.sort();
+        let mut symbols = self.symbols.clone();
+        symbols.sort();
+        let mut quote_assets = self.quote_assets.clone();
+        quote_assets.sort();
+        format!(
+            "p={};s={};st={};q={}",
+            permissions.join(","),
+            symbols.join(","),
+            self.symbol_status.as_deref().unwrap_or_default(),
+            quote_assets.join(",")
+        )
+    }
+
+    /// 缓存文件名，默认查询范围使用exchange_info.json，其它查询范围根据cache_key的哈希值命名
+    pub(crate) fn cache_file_name(&self) -> String {
+        let key = self.cache_key();
+        if key == Self::default().cache_key() {
+            return "exchange_info.json".to_string();
+        }
+
+        // FNV-1a，保证不同进程、不同版本的程序得到相同的文件名
+        let hash = key.bytes().fold(0xcbf29ce484222325u64, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+        format!("exchange_info_{hash:016x}.json")
+    }
+}
+
+/// exchange_info缓存的存放位置
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ExchangeInfoCacheLocation {
+    /// 应用目录下的bian目录
+    #[default]
+    AppDir,
+    /// 给定的目录
+    Dir(PathBuf),
+    /// 只缓存在内存中，由所有RestConn克隆共享，适用于只读文件系统
+    Memory,
+    /// 不缓存，每次都请求新数据
+    Disabled,
+}
+
+/// exchange_info的缓存策略，默认缓存在应用目录下，有效期为半小时
+///
+/// ```rust
+/// // 缓存到/tmp/bian目录，有效期10分钟
+/// let policy = ExchangeInfoCachePolicy::dir("/tmp/bian").ttl(Duration::from_secs(600));
+/// // 只读文件系统中只缓存在内存中
+/// let policy = ExchangeInfoCachePolicy::memory();
+/// // 不缓存
+/// let policy = ExchangeInfoCachePolicy::disabled();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExchangeInfoCachePolicy {
+    pub location: ExchangeInfoCacheLocation,
+    /// 缓存的有效期，磁盘缓存根据文件的mtime判断，内存缓存根据写入时间判断
+    pub ttl: Duration,
+}
+
+impl Default for ExchangeInfoCachePolicy {
+    fn default() -> Self {
+        Self {
+            location: ExchangeInfoCacheLocation::AppDir,
+            ttl: Duration::from_secs(1800),
+        }
+    }
+}
+
+impl ExchangeInfoCachePolicy {
+    /// 缓存到给定目录
+    pub fn dir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            location: ExchangeInfoCacheLocation::Dir(dir.into()),
+            ..Default::default()
+        }
+    }
+
+    /// 只缓存在内存中
+    pub fn memory() -> Self {
+        Self {
+            location: ExchangeInfoCacheLocation::Memory,
+            ..Default::default()
+        }
+    }
+
+    /// 不缓存
+    pub fn disabled() -> Self {
+        Self {
+            location: ExchangeInfoCacheLocation::Disabled,
+            ..Default::default()
+        }
+    }
+
+    /// 设置缓存的有效期
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+}
+
+/// 按缓存策略读写exchange_info缓存，所有RestConn克隆共享
+#[derive(Clone)]
+pub(crate) struct ExchangeInfoCache {
+    policy: Arc<ExchangeInfoCachePolicy>,
+    /// 判断缓存是否过期时的当前时间
+    clock: Arc<dyn Clock>,
+    /// 内存缓存，key为ExchangeInfoQuery::cache_key()，value为写入时间和序列化后的exchange_info
+    memory: Arc<std::sync::Mutex<HashMap<String, (SystemTime, String)>>>,
+}
+
+impl ExchangeInfoCache {
+    pub(crate) fn new(policy: ExchangeInfoCachePolicy, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            policy: Arc::new(policy),
+            clock,
+            memory: Arc::default(),
+        }
+    }
+
+    /// 磁盘缓存所在的目录，不使用磁盘缓存时返回None
+    fn dir(&self) -> Option<PathBuf> {
+        match &self.policy.location {
+            ExchangeInfoCacheLocation::AppDir => Some(app_dir().unwrap().join("bian")),
+            ExchangeInfoCacheLocation::Dir(dir) => Some(dir.clone()),
+            ExchangeInfoCacheLocation::Memory | ExchangeInfoCacheLocation::Disabled => None,
+        }
+    }
+
+    /// 是否已超过有效期
+    fn expired(&self, written_at: SystemTime) -> bool {
+        self.clock
+            .system_time()
+            .duration_since(written_at)
+            .map_or(true, |age| age > self.policy.ttl)
+    }
+
+    /// 读取给定查询范围的缓存，缓存不存在、已过期或无法解析时返回None
+    pub(crate) async fn load(&self, query: &ExchangeInfoQuery) -> Option<ExchangeInfo> {
+        let data = match &self.policy.location {
+            ExchangeInfoCacheLocation::Disabled => return None,
+            ExchangeInfoCacheLocation::Memory => {
+                let memory = self.memory.lock().unwrap();
+                let (written_at, data) = memory.get(&query.cache_key())?;
+                if self.expired(*written_at) {
+                    return None;
+                }
+                data.clone()
+            }
+            ExchangeInfoCacheLocation::AppDir | ExchangeInfoCacheLocation::Dir(_) => {
+                let file = self.dir()?.join(query.cache_file_name());
+                let mtime = fs::metadata(&file).await.ok()?.modified().ok()?;
+                if self.expired(mtime) {
+                    return None;
+                }
+                fs::read_to_string(&file).await.ok()?
+            }
+        };
+        serde_json::from_str(&data).ok()
+    }
+
+    /// 写入给定查询范围的缓存，写入失败时只记录日志
+    pub(crate) async fn save(&self, query: &ExchangeInfoQuery, exchange_info: &ExchangeInfo) {
+        if self.policy.location == ExchangeInfoCacheLocation::Disabled {
+            return;
+        }
+        let data = match serde_json::to_string(exchange_info) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::error!("can't serialize exchange_info: {e}");
+                return;
+            }
+        };
+
+        match self.dir() {
+            None => {
+                let mut memory = self.memory.lock().unwrap();
+                memory.insert(query.cache_key(), (self.clock.system_time(), data));
+            }
+            Some(dir) => {
+                let file = dir.join(query.cache_file_name());
+                if let Err(e) = write_atomic(&dir, &file, data.as_bytes()).await {
+                    warn!("can't write exchange_info cache {}: {e}", file.display());
+                }
+            }
+        }
+    }
+}
+
+/// 先写入同目录下的临时文件，再重命名为目标文件，
+/// 保证其它进程不会读取到写了一半的文件
+async fn write_atomic(dir: &Path, file: &Path, data: &[u8]) -> std::io::Result<()> {
+    fs::create_dir_all(dir).await?;
+    let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+    let tmp_file = dir.join(format!(
+        ".{file_name}.{}.tmp",
+        uuid::Uuid::new_v4().simple()
+    ));
+    if let Err(e) = fs::write(&tmp_file, data).await {
+        let _ = fs::remove_file(&tmp_file).await;
+        return Err(e);
+    }
+    if let Err(e) = fs::rename(&tmp_file, file).await {
+        let _ = fs::remove_file(&tmp_file).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// 两次exchange_info之间的变化
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExchangeInfoEvent {
+    /// 新上线的交易对
+    SymbolAdded { symbol: String },
+    /// 已下线(不再出现在exchange_info中)的交易对
+    SymbolRemoved { symbol: String },
+    /// 交易对状态变化，例如TRADING -> BREAK、TRADING -> HALT
+    StatusChanged {
+        symbol: String,
+        old: String,
+        new: String,
+    },
+    /// 交易对的过滤器变化，例如PRICE_FILTER(tickSize)、LOT_SIZE、NOTIONAL/MIN_NOTIONAL，
+    /// old为None表示新增的过滤器，new为None表示被移除的过滤器
+    FilterChanged {
+        symbol: String,
+        filter_type: String,
+        old: Option<Value>,
+        new: Option<Value>,
+    },
+    /// 是否允许杠杆交易发生变化
+    MarginToggled { symbol: String, allowed: bool },
+}
+
+/// 比较新旧两个版本的exchange_info，返回其中的变化
+pub fn diff_exchange_info(old: &ExchangeInfo, new: &ExchangeInfo) -> Vec<ExchangeInfoEvent> {
+    let old_symbols: HashMap<&str, &SymbolInfo> = old
+        .symbols
+        .iter()
+        .map(|si| (si.symbol.as_str(), si))
+        .collect();
+    let new_symbols: HashMap<&str, &SymbolInfo> = new
+        .symbols
+        .iter()
+        .map(|si| (si.symbol.as_str(), si))
+        .collect();
+
+    let mut events = vec![];
+    for si in &new.symbols {
+        let symbol = si.symbol.as_str();
+        let Some(old_si) = old_symbols.get(symbol) else {
+            events.push(ExchangeInfoEvent::SymbolAdded {
+                symbol: symbol.to_string(),
+            });
+            continue;
+        };
+
+        let (old_status, new_status) =
+            (to_plain_string(&old_si.status), to_plain_string(&si.status));
+        if old_status != new_status {
+            events.push(ExchangeInfoEvent::StatusChanged {
+                symbol: symbol.to_string(),
+                old: old_status,
+                new: new_status,
+            });
+        }
+
+        let old_filters = filters_by_type(&old_si.filters);
+        let new_filters = filters_by_type(&si.filters);
+        for (filter_type, new_filter) in &new_filters {
+            let old_filter = old_filters.get(filter_type);
+            if old_filter != Some(new_filter) {
+                events.push(ExchangeInfoEvent::FilterChanged {
+                    symbol: symbol.to_string(),
+                    filter_type: filter_type.clone(),
+                    old: old_filter.cloned(),
+                    new: Some(new_filter.clone()),
+                });
+            }
+        }
+        for (filter_type, old_filter) in &old_filters {
+            if !new_filters.contains_key(filter_type) {
+                events.push(ExchangeInfoEvent::FilterChanged {
+                    symbol: symbol.to_string(),
+                    filter_type: filter_type.clone(),
+                    old: Some(old_filter.clone()),
+                    new: None,
+                });
+            }
+        }
+
+        if old_si.is_margin_trading_allowed != si.is_margin_trading_allowed {
+            events.push(ExchangeInfoEvent::MarginToggled {
+                symbol: symbol.to_string(),
+                allowed: si.is_margin_trading_allowed,
+            });
+        }
+    }
+
+    for si in &old.symbols {
+        if !new_symbols.contains_key(si.symbol.as_str()) {
+            events.push(ExchangeInfoEvent::SymbolRemoved {
+                symbol: si.symbol.clone(),
+            });
+        }
+    }
+
+    events
+}
+
+/// 序列化为字符串，例如交易对状态`Trading`序列化为"TRADING"
+fn to_plain_string<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(s)) => s,
+        Ok(v) => v.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// 将过滤器列表按filterType分组，无法识别filterType的过滤器使用其索引作为key
+fn filters_by_type<T: Serialize>(filters: &T) -> HashMap<String, Value> {
+    let Ok(Value::Array(filters)) = serde_json::to_value(filters) else {
+        return HashMap::new();
+    };
+    filters
+        .into_iter()
+        .enumerate()
+        .map(|(i, f)| {
+            let filter_type = f
+                .get("filterType")
+                .and_then(Value::as_str)
+                .map(String::from)
+                .unwrap_or_else(|| i.to_string());
+            (filter_type, f)
+        })
+        .collect()
+}
+
+/// 某个版本的exchange_info，以及交易对名称到其在symbols中的索引
+struct ExchangeInfoSnapshot {
+    exchange_info: Arc<ExchangeInfo>,
+    index: HashMap<String, usize>,
+}
+
+impl ExchangeInfoSnapshot {
+    fn new(exchange_info: ExchangeInfo) -> Self {
+        let index = exchange_info
+            .symbols
+            .iter()
+            .enumerate()
+            .map(|(i, si)| (si.symbol.clone(), i))
+            .collect();
+        Self {
+            exchange_info: Arc::new(exchange_info),
+            index,
+        }
+    }
+}
+
+/// 所有RestConn克隆之间共享的exchange_info
+///
+/// 内部是可原子替换的指针，任何一个克隆更新后，其它克隆立即可见，
+/// 读取时不加锁，已经取出的旧版本在用完之前仍然有效
+#[derive(Clone)]
+pub(crate) struct SharedExchangeInfo {
+    current: Arc<ArcSwapOption<ExchangeInfoSnapshot>>,
+    /// 避免多个任务同时发起首次加载
+    load_lock: Arc<Mutex<()>>,
+    /// 每次更新后，将新旧版本之间的变化发送到该通道
+    events: broadcast::Sender<ExchangeInfoEvent>,
+}
+
+impl Default for SharedExchangeInfo {
+    fn default() -> Self {
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        Self {
+            current: Arc::default(),
+            load_lock: Arc::default(),
+            events,
+        }
+    }
+}
+
+impl SharedExchangeInfo {
+    /// 当前版本的exchange_info，尚未加载时返回None
+    pub(crate) fn get(&self) -> Option<Arc<ExchangeInfo>> {
+        self.current
+            .load()
+            .as_ref()
+            .map(|snapshot| snapshot.exchange_info.clone())
+    }
+
+    /// 替换为新版本的exchange_info，并发送与旧版本之间的变化(首次加载时不发送)
+    pub(crate) fn store(&self, exchange_info: ExchangeInfo) {
+        let snapshot = Arc::new(ExchangeInfoSnapshot::new(exchange_info));
+        let old = self.current.swap(Some(snapshot.clone()));
+
+        // 没有订阅者时，不需要计算变化
+        if let Some(old) = old
+            && self.events.receiver_count() > 0
+        {
+            let events = diff_exchange_info(&old.exchange_info, &snapshot.exchange_info);
+            if !events.is_empty() {
+                info!("exchange_info changed: {} events", events.len());
+            }
+            for event in events {
+                let _ = self.events.send(event);
+            }
+        }
+    }
+
+    /// 订阅exchange_info的变化
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<ExchangeInfoEvent> {
+        self.events.subscribe()
+    }
+
+    /// 获取交易对的信息，不会克隆SymbolInfo
+    pub(crate) fn symbol_info(&self, symbol: &str) -> Option<SymbolInfoRef> {
+        let guard = self.current.load();
+        let snapshot = guard.as_ref()?;
+        let idx = *snapshot.index.get(symbol)?;
+        Some(SymbolInfoRef {
+            exchange_info: snapshot.exchange_info.clone(),
+            idx,
+        })
+    }
+
+    /// 首次加载时持有的锁，获取锁后应再次检查是否已经被其它任务加载
+    pub(crate) async fn lock_load(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.load_lock.lock().await
+    }
+}
+
+/// 对某个版本exchange_info中单个交易对信息的引用
+///
+/// 持有该版本exchange_info的Arc，即使exchange_info在此期间被更新，该引用仍然有效
+#[derive(Clone)]
+pub struct SymbolInfoRef {
+    exchange_info: Arc<ExchangeInfo>,
+    idx: usize,
+}
+
+impl Deref for SymbolInfoRef {
+    type Target = SymbolInfo;
+
+    fn deref(&self) -> &Self::Target {
+        &self.exchange_info.symbols[self.idx]
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_write_atomic() {
+        let dir = std::env::temp_dir().join(format!("ba_api_{}", uuid::Uuid::new_v4().simple()));
+        let file = dir.join("exchange_info.json");
+        write_atomic(&dir, &file, b"{}").await.unwrap();
+        write_atomic(&dir, &file, b"[]").await.unwrap();
+        assert_eq!(fs::read_to_string(&file).await.unwrap(), "[]");
+
+        // 不应残留临时文件
+        let mut entries = fs::read_dir(&dir).await.unwrap();
+        let mut count = 0;
+        while entries.next_entry().await.unwrap().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 1);
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+    /// 币安响应中的交易对信息
+    fn symbol(
+        symbol: &str,
+        status: &str,
+        tick_size: &str,
+        step_size: &str,
+        min_notional: &str,
+        margin: bool,
+    ) -> String {
+        format!(
+            r#"{{
+                "symbol": "{symbol}",
+                "status": "{status}",
+                "baseAsset": "{base}",
+                "baseAssetPrecision": 8,
+                "quoteAsset": "USDT",
+                "quotePrecision": 8,
+                "quoteAssetPrecision": 8,
+                "baseCommissionPrecision": 8,
+                "quoteCommissionPrecision": 8,
+                "orderTypes": ["LIMIT", "MARKET"],
+                "icebergAllowed": true,
+                "ocoAllowed": true,
+                "otoAllowed": true,
+                "quoteOrderQtyMarketAllowed": true,
+                "allowTrailingStop": true,
+                "cancelReplaceAllowed": true,
+                "isSpotTradingAllowed": true,
+                "isMarginTradingAllowed": {margin},
+                "filters": [
+                    {{ "filterType": "PRICE_FILTER", "minPrice": "0.01000000", "maxPrice": "1000000.00000000", "tickSize": "{tick_size}" }},
+                    {{ "filterType": "LOT_SIZE", "minQty": "0.00001000", "maxQty": "9000.00000000", "stepSize": "{step_size}" }},
+                    {{ "filterType": "NOTIONAL", "minNotional": "{min_notional}", "applyMinToMarket": true, "maxNotional": "9000000.00000000", "applyMaxToMarket": false, "avgPriceMins": 5 }}
+                ],
+                "permissions": [],
+                "permissionSets": [["SPOT"]],
+                "defaultSelfTradePreventionMode": "EXPIRE_MAKER",
+                "allowedSelfTradePreventionModes": ["EXPIRE_TAKER", "EXPIRE_MAKER", "EXPIRE_BOTH"]
+            }}"#,
+            base = symbol.trim_end_matches("USDT"),
+        )
+    }
+
+    fn exchange_info(symbols: &[String]) -> ExchangeInfo {
+        parse_exchange_info(&format!(
+            r#"{{ "timezone": "UTC", "serverTime": 1692918000000, "rateLimits": [], "exchangeFilters": [], "symbols": [{}] }}"#,
+            symbols.join(",")
+        ))
+        .unwrap()
+    }
+
+    fn btc() -> String {
+        symbol(
+            "BTCUSDT",
+            "TRADING",
+            "0.01000000",
+            "0.00001000",
+            "5.00000000",
+            true,
+        )
+    }
+
+    /// BTCUSDT从btc()变为new时的变化
+    fn diff_btc(new: String) -> Vec<ExchangeInfoEvent> {
+        diff_exchange_info(&exchange_info(&[btc()]), &exchange_info(&[new]))
+    }
+
+    /// 过滤器变化事件中的过滤器类型，以及新过滤器中field的值
+    fn filter_changed(events: &[ExchangeInfoEvent], field: &str) -> Vec<(String, String)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                ExchangeInfoEvent::FilterChanged {
+                    symbol,
+                    filter_type,
+                    old: Some(_),
+                    new: Some(new),
+                } if symbol == "BTCUSDT" => {
+                    Some((filter_type.clone(), new.get(field)?.as_str()?.to_string()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let eth = symbol(
+            "ETHUSDT",
+            "TRADING",
+            "0.01000000",
+            "0.00010000",
+            "5.00000000",
+            true,
+        );
+        let info = exchange_info(&[btc(), eth.clone()]);
+        assert!(diff_exchange_info(&info, &exchange_info(&[btc(), eth])).is_empty());
+    }
+
+    #[test]
+    fn test_diff_symbols() {
+        let eth = symbol(
+            "ETHUSDT",
+            "TRADING",
+            "0.01000000",
+            "0.00010000",
+            "5.00000000",
+            true,
+        );
+        let bnb = symbol(
+            "BNBUSDT",
+            "TRADING",
+            "0.01000000",
+            "0.00100000",
+            "5.00000000",
+            false,
+        );
+        let events =
+            diff_exchange_info(&exchange_info(&[btc(), eth]), &exchange_info(&[btc(), bnb]));
+        assert_eq!(
+            events,
+            vec![
+                ExchangeInfoEvent::SymbolAdded {
+                    symbol: "BNBUSDT".to_string()
+                },
+                ExchangeInfoEvent::SymbolRemoved {
+                    symbol: "ETHUSDT".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff_status() {
+        let events = diff_btc(symbol(
+            "BTCUSDT",
+            "HALT",
+            "0.01000000",
+            "0.00001000",
+            "5.00000000",
+            true,
+        ));
+        assert_eq!(
+            events,
+            vec![ExchangeInfoEvent::StatusChanged {
+                symbol: "BTCUSDT".to_string(),
+                old: "TRADING".to_string(),
+                new: "HALT".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_diff_filters() {
+        // tickSize变化
+        let events = diff_btc(symbol(
+            "BTCUSDT",
+            "TRADING",
+            "0.10000000",
+            "0.00001000",
+            "5.00000000",
+            true,
+        ));
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            filter_changed(&events, "tickSize"),
+            vec![("PRICE_FILTER".to_string(), "0.10000000".to_string())]
+        );
+
+        // stepSize变化
+        let events = diff_btc(symbol(
+            "BTCUSDT",
+            "TRADING",
+            "0.01000000",
+            "0.00010000",
+            "5.00000000",
+            true,
+        ));
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            filter_changed(&events, "stepSize"),
+            vec![("LOT_SIZE".to_string(), "0.00010000".to_string())]
+        );
+
+        // 最小名义价值变化
+        let events = diff_btc(symbol(
+            "BTCUSDT",
+            "TRADING",
+            "0.01000000",
+            "0.00001000",
+            "10.00000000",
+            true,
+        ));
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            filter_changed(&events, "minNotional"),
+            vec![("NOTIONAL".to_string(), "10.00000000".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_diff_margin() {
+        let events = diff_btc(symbol(
+            "BTCUSDT",
+            "TRADING",
+            "0.01000000",
+            "0.00001000",
+            "5.00000000",
+            false,
+        ));
+        assert_eq!(
+            events,
+            vec![ExchangeInfoEvent::MarginToggled {
+                symbol: "BTCUSDT".to_string(),
+                allowed: false,
+            }]
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use reqwest::Url;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// 币安现货REST的备用地址，与`ba_global::REST_BASE_URL`等价
+pub const REST_BACKUP_URLS: [&str; 5] = [
+    "https://api1.binance.com",
+    "https://api2.binance.com",
+    "https://api3.binance.com",
+    "https://api4.binance.com",
+    "https://api-gcp.binance.com",
+];
+
+/// 连续失败多少次后，认为该地址不健康
+const UNHEALTHY_FAILURES: u32 = 3;
+/// 失败的地址在多久之后可以重新被选中
+const FAILURE_COOLDOWN: Duration = Duration::from_secs(30);
+/// 平均延迟中最新一次延迟所占的比重
+const LATENCY_ALPHA: f64 = 0.2;
+
+/// 单个REST地址的健康统计
+#[derive(Debug, Clone)]
+pub struct HostStats {
+    pub url: Url,
+    /// 指数加权的平均延迟，尚未成功请求过时为None
+    pub latency: Option<Duration>,
+    /// 成功的请求次数(收到了响应，包括4xx)
+    pub successes: u64,
+    /// 失败的请求次数(连接失败、超时、5xx)
+    pub failures: u64,
+    /// 连续失败的次数，成功一次后清零
+    pub consecutive_failures: u32,
+    /// 最近一次失败的时间
+    pub last_failure: Option<Instant>,
+}
+
+impl HostStats {
+    fn new(url: Url) -> Self {
+        Self {
+            url,
+            latency: None,
+            successes: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            last_failure: None,
+        }
+    }
+
+    /// 是否健康：连续失败次数未达到上限，或者距离最近一次失败已经超过了冷却时间
+    pub fn is_healthy(&self) -> bool {
+        self.consecutive_failures < UNHEALTHY_FAILURES || self.cooled_down()
+    }
+
+    fn cooled_down(&self) -> bool {
+        self.last_failure
+            .is_none_or(|x| x.elapsed() >= FAILURE_COOLDOWN)
+    }
+
+    /// 用于选择地址的排序键，越小越优先：
+    /// 冷却中的连续失败次数少的优先，其次是平均延迟低的，尚未测量延迟的排在已测量的之后
+    fn rank(&self) -> (u32, bool, Duration) {
+        let failures = if self.cooled_down() {
+            0
+        } else {
+            self.consecutive_failures
+        };
+        (
+            failures,
+            self.latency.is_none(),
+            self.latency.unwrap_or_default(),
+        )
+    }
+}
+
+/// 多个等价的REST地址，所有RestConn克隆共享统计信息
+#[derive(Debug, Clone)]
+pub(crate) struct Hosts {
+    hosts: Arc<Vec<Mutex<HostStats>>>,
+}
+
+impl Hosts {
+    /// urls不能为空
+    pub(crate) fn new(urls: Vec<Url>) -> Self {
+        assert!(!urls.is_empty(), "at least one base url is required");
+        Self {
+            hosts: Arc::new(
+                urls.into_iter()
+                    .map(|x| Mutex::new(HostStats::new(x)))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.hosts.len()
+    }
+
+    pub(crate) fn url(&self, idx: usize) -> Url {
+        self.hosts[idx].lock().unwrap().url.clone()
+    }
+
+    /// 选择最健康且延迟最低的地址，返回其索引
+    pub(crate) fn select(&self) -> usize {
+        (0..self.hosts.len())
+            .min_by_key(|&i| self.hosts[i].lock().unwrap().rank())
+            .unwrap_or_default()
+    }
+
+    /// 收到了响应
+    pub(crate) fn record_success(&self, idx: usize, latency: Duration) {
+        let mut host = self.hosts[idx].lock().unwrap();
+        host.successes += 1;
+        host.consecutive_failures = 0;
+        host.latency = Some(match host.latency {
+            Some(x) => x.mul_f64(1.0 - LATENCY_ALPHA) + latency.mul_f64(LATENCY_ALPHA),
+            None => latency,
+        });
+    }
+
+    /// 连接失败、超时或5xx
+    pub(crate) fn record_failure(&self, idx: usize) {
+        let mut host = self.hosts[idx].lock().unwrap();
+        host.failures += 1;
+        host.consecutive_failures += 1;
+        host.last_failure = Some(Instant::now());
+    }
+
+    pub(crate) fn stats(&self) -> Vec<HostStats> {
+        self.hosts
+            .iter()
+            .map(|x| x.lock().unwrap().clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let urls = ["https://api1.binance.com", "https://api2.binance.com"];
+        let hosts = Hosts::new(urls.iter().map(|x| Url::parse(x).unwrap()).collect());
+        assert_eq!(hosts.select(), 0);
+
+        // 延迟低的优先
+        hosts.record_success(0, Duration::from_millis(200));
+        hosts.record_success(1, Duration::from_millis(50));
+        assert_eq!(hosts.select(), 1);
+
+        // 失败后切换到其它地址
+        hosts.record_failure(1);
+        assert_eq!(hosts.select(), 0);
+        hosts.record_failure(0);
+        hosts.record_failure(0);
+        assert_eq!(hosts.select(), 1);
+
+        let stats = hosts.stats();
+        assert_eq!(stats[0].failures, 2);
+        assert_eq!(stats[1].consecutive_failures, 1);
+        assert!(stats[0].is_healthy());
+        hosts.record_failure(0);
+        assert!(!hosts.stats()[0].is_healthy());
+    }
+}
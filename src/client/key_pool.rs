@@ -0,0 +1,100 @@
+use super::{
+    secret::Secret,
+    signer::{Signer, sec_key_signer},
+};
+use crate::ApiSecKey;
+use std::{fmt::Debug, sync::Arc};
+
+/// 多个账户的key，一个RestConn可以通过KeyPool为多个账户发送请求
+///
+/// 每个账户有各自的下单次数限速(UID限速)，但所有账户共享IP的权重限速
+///
+/// ```rust
+/// let pool = KeyPool::new()
+///     .add_sec_key("main", api_key1, sec_key1)
+///     .add_with_signer("sub1", ApiSecKey::new(Some(api_key2), None), UnixSocketSigner::new(path));
+/// let rest_conn = RestConn::builder(api_sec_key).key_pool(pool).build().await?;
+///
+/// // 使用指定账户下单
+/// rest_conn.with_key("sub1")?.order(...).await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct KeyPool {
+    keys: Vec<PoolKey>,
+}
+
+#[derive(Clone)]
+pub(crate) struct PoolKey {
+    pub(crate) name: String,
+    pub(crate) api_sec_key: ApiSecKey,
+    pub(crate) signer: Arc<dyn Signer>,
+}
+
+impl KeyPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加一个账户的key，使用ApiSecKey签名，名称重复时替换原来的key，
+    /// ApiSecKey中的secret key是普通的String，需要drop时清零内存时使用`add_sec_key()`
+    pub fn add(self, name: impl Into<String>, api_sec_key: ApiSecKey) -> Self {
+        let signer = Arc::new(api_sec_key.clone());
+        self.insert(name.into(), api_sec_key, signer)
+    }
+
+    /// 添加一个账户的key，secret key保存在`Secret`中，drop时清零内存，
+    /// 根据secret key的格式使用HMAC-SHA256或Ed25519签名，见`sec_key_signer()`
+    pub fn add_sec_key(
+        self,
+        name: impl Into<String>,
+        api_key: impl Into<String>,
+        sec_key: impl Into<Secret>,
+    ) -> Self {
+        let api_sec_key = ApiSecKey::new(Some(api_key.into()), None);
+        self.insert(name.into(), api_sec_key, sec_key_signer(sec_key))
+    }
+
+    /// 添加一个账户的key，使用指定的Signer签名，此时ApiSecKey只需提供api_key
+    pub fn add_with_signer(
+        self,
+        name: impl Into<String>,
+        api_sec_key: ApiSecKey,
+        signer: impl Signer + 'static,
+    ) -> Self {
+        self.insert(name.into(), api_sec_key, Arc::new(signer))
+    }
+
+    fn insert(mut self, name: String, api_sec_key: ApiSecKey, signer: Arc<dyn Signer>) -> Self {
+        self.keys.retain(|x| x.name != name);
+        self.keys.push(PoolKey {
+            name,
+            api_sec_key,
+            signer,
+        });
+        self
+    }
+
+    /// 所有key的名称
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|x| x.name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&PoolKey> {
+        self.keys.iter().find(|x| x.name == name)
+    }
+}
+
+/// Debug时只输出key的名称
+impl Debug for KeyPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
@@ -5,12 +5,18 @@ pub mod rest;
 #[cfg(feature = "websocket")]
 pub mod websocket;
 
+/// 所有RestConn克隆共享的exchange_info
+pub mod exchange_info;
+
 /// Rest请求的参数和签名鉴权类型
 ///
 /// 所有请求都需要实现Serialize和Param Trait，
 /// 如果请求的参数为空，则定义为空的Struct并实现这两个Trait
 pub mod params;
 
+/// 带有类型的REST接口，下游可为自定义参数实现Endpoint以调用尚未提供的接口
+pub mod endpoint;
+
 /// [行情接口](rest/struct.RestConn.html#impl-1)，币安API Doc行情接口下的方法都在此
 pub mod market_data;
 
@@ -23,12 +29,52 @@ pub mod sub_account;
 /// 钱包相关接口
 pub mod wallet;
 
-/// 限速规则
-// pub mod rate_limit;
-pub(crate) mod rate_limit;
+/// 请求失败时的重试策略
+pub mod retry;
+
+/// 当前时间的来源，测试时可以手动推进
+pub mod clock;
+
+/// 本地时间与服务器时间的同步
+pub mod clock_sync;
+
+/// 签名请求的签名方式
+pub mod signer;
+
+/// 敏感信息的包装，避免私钥、签名等出现在日志中
+pub mod secret;
+
+/// 多个账户的key
+pub mod key_pool;
+
+/// 多个等价的REST地址及其健康统计
+pub mod hosts;
+
+/// 多个进程共享的限速预算
+#[cfg(feature = "shared-rate-limit")]
+mod shared_limit;
+
+/// 限速规则，以及限速状态的快照和使用率阈值
+pub mod rate_limit;
+
+/// 各接口的权重表，可以在运行时覆盖
+pub mod weight;
 // pub mod websocket1;
 
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use clock_sync::ClockSync;
+pub use endpoint::Endpoint;
+pub use hosts::{HostStats, REST_BACKUP_URLS};
+pub use key_pool::KeyPool;
+pub use rate_limit::{
+    BucketKind, BucketSnapshot, Interval, Quota, RateLimitEvent, RateLimitParam, RequestPriority,
+    ScopeStats, Threshold,
+};
 pub use rest::*;
+pub use retry::{ExponentialBackoff, NoRetry, RetryPolicy};
+pub use secret::Secret;
+pub use signer::{Ed25519Signer, HmacSigner, Signer, sec_key_signer};
+pub use weight::{WeightRule, WeightTable};
 #[cfg(feature = "websocket")]
 pub use websocket::*;
 
@@ -1,12 +1,33 @@
 #![allow(clippy::new_without_default)]
 
-use super::timestamp;
+use super::{
+    RestMethod,
+    clock::{Clock, SystemClock},
+    endpoint::Endpoint,
+    exchange_info::ExchangeInfoQuery,
+    secret::redact,
+    signer::Signer,
+};
 use crate::{
-    ApiSecKey, KLineInterval, Permission, SubAccountType,
+    ApiSecKey, KLineInterval, KLines, Permission, SubAccountType,
     errors::{BiAnApiError, BiAnResult},
-    types::order::{OrderRespType, OrderSide, OrderType, TimeInForce},
+    types::{
+        account::Account,
+        depth::Depth,
+        order::{
+            AggTrade, CancelOpenOrdersInfo, CancelOrderInfo, HistoricalTrade, MyTrades, Order,
+            OrderInfo, OrderRespType, OrderSide, OrderType, TimeInForce, Trade,
+        },
+        other_types::{AvgPrice, Prices, ServerTime},
+        sub_account::{SubAccountBalances, SubAccounts, UniversalTransfer},
+        symbol_info::{DelistSchedule, ExchangeInfo},
+        ticker::{BookTickers, FullTickers},
+        wallet::{Dust, DustBtc},
+    },
 };
-use serde::{Serialize, ser::SerializeStruct};
+use ba_types::{RateLimit, types::sub_account::AccountInfo};
+use serde::{Serialize, Serializer, ser::SerializeStruct};
+use std::{fmt::Debug, sync::Arc, time::Duration};
 use uuid::Uuid;
 
 /// 将Symbol列表转换为URL参数字符串
@@ -20,6 +41,114 @@ fn list_2_str(symbols: Vec<&str>) -> Option<String> {
     Some(format!("[{}]", j.join(",")))
 }
 
+/// 参数中指定的交易对数量，symbol和symbols都为None(所有交易对)时返回None
+fn symbol_arg(symbol: &Option<String>, symbols: &Option<String>) -> Option<u32> {
+    match (symbol, symbols) {
+        (Some(_), _) => Some(1),
+        (None, Some(symbols)) => Some(symbols.matches(',').count() as u32 + 1),
+        (None, None) => None,
+    }
+}
+
+/// 签名请求默认的recvWindow(毫秒)
+pub const DEFAULT_RECV_WINDOW: u32 = 5000;
+
+/// 签名请求的recvWindow，精确到微秒
+///
+/// 币安允许recvWindow带最多3位小数(例如6000.346毫秒)，最大为60000毫秒，超出时取60000毫秒
+/// ```rust
+/// let rw = RecvWindow::millis(1000);
+/// let rw = RecvWindow::micros(6_000_346);
+/// let rw: RecvWindow = Duration::from_secs(60).into();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RecvWindow(u64);
+
+impl RecvWindow {
+    /// 币安允许的最大recvWindow
+    pub const MAX: RecvWindow = RecvWindow(60_000_000);
+
+    pub fn millis(ms: u32) -> Self {
+        Self::micros(ms as u64 * 1000)
+    }
+
+    pub fn micros(us: u64) -> Self {
+        Self(us.min(Self::MAX.0))
+    }
+
+    pub fn as_micros(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for RecvWindow {
+    fn default() -> Self {
+        Self::millis(DEFAULT_RECV_WINDOW)
+    }
+}
+
+impl From<u32> for RecvWindow {
+    fn from(ms: u32) -> Self {
+        Self::millis(ms)
+    }
+}
+
+impl From<Duration> for RecvWindow {
+    fn from(d: Duration) -> Self {
+        Self::micros(d.as_micros().try_into().unwrap_or(u64::MAX))
+    }
+}
+
+impl Serialize for RecvWindow {
+    /// 整毫秒时序列化为整数，否则序列化为带3位小数的毫秒数
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.0.is_multiple_of(1000) {
+            serializer.serialize_u64(self.0 / 1000)
+        } else {
+            serializer.serialize_f64(self.0 as f64 / 1000.0)
+        }
+    }
+}
+
+/// 签名请求中timestamp的单位
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimestampUnit {
+    #[default]
+    Millisecond,
+    Microsecond,
+}
+
+/// 签名请求的选项
+#[derive(Debug, Clone, Default)]
+pub struct SignOptions {
+    /// recvWindow，参数自身通过`Param::recv_window()`指定了recvWindow时，使用参数的值
+    pub recv_window: RecvWindow,
+    /// timestamp的单位
+    pub timestamp_unit: TimestampUnit,
+    /// 服务器时间与本地时间之差(毫秒)，签名时的timestamp将加上该值，参考`ClockSync`
+    pub time_offset: i64,
+    /// 当前时间的来源，None时使用系统时间
+    pub clock: Option<Arc<dyn Clock>>,
+}
+
+impl SignOptions {
+    /// 校正后的timestamp，单位由timestamp_unit决定
+    fn timestamp(&self) -> u128 {
+        let now = match &self.clock {
+            Some(clock) => clock.now_micros(),
+            None => SystemClock.now_micros(),
+        };
+        let ts = match self.timestamp_unit {
+            TimestampUnit::Millisecond => now.div_euclid(1000) as i128 + self.time_offset as i128,
+            TimestampUnit::Microsecond => now as i128 + self.time_offset as i128 * 1000,
+        };
+        ts.max(0) as u128
+    }
+}
+
 /// 接口鉴权类型  
 /// None无需API KEY鉴权，也无需SECRET KEY签名  
 /// UserStream和MarketData需API KEY鉴权，但无需SECRET KEY签名  
@@ -41,10 +170,12 @@ pub enum CheckType {
     UserData,
 }
 
+/// 请求的限速方式，/sapi/*接口据此选择消耗IP权重还是UID权重，/api/*接口的权重总是按IP计数
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PRateLimit {
-    /// /api/*接口的IP限速方式
+    /// 按IP计数
     ApiIp,
-    /// /api/*接口的UID限速方式
+    /// 按UID计数，每个账户单独计数
     ApiUid,
 }
 
@@ -62,33 +193,48 @@ pub trait Param {
         PRateLimit::ApiIp
     }
 
-    fn payload(&self, api_sec_key: &ApiSecKey) -> PWrapperParams<'_, Self>
+    /// 该请求使用的recvWindow，返回None时使用客户端的默认值，
+    /// 例如下单时可使用较小的值，查询历史数据时可使用较大的值
+    fn recv_window(&self) -> Option<RecvWindow> {
+        None
+    }
+
+    /// 生成请求的payload，需要签名的请求将使用给定的recvWindow和时间偏移，并通过signer签名
+    fn payload<'a>(
+        &'a self,
+        signer: &'a dyn Signer,
+        opts: &'a SignOptions,
+    ) -> impl Future<Output = BiAnResult<PWrapperParams<'a, Self>>> + 'a
     where
         Self: Serialize + Sized,
     {
-        // rest api的api key总是在http header中设置，且签名不需要api_key也作为payload的一部分，所以这里不设置api key
-        let mut wraped_params = PWrapperParams {
-            api_key: None,
-            signature: None,
-            timestamp: None,
-            recv_window: None,
-            params: self,
-        };
-        match self.check_type() {
-            // 不需要签名的
-            CheckType::None | CheckType::UserStream | CheckType::MarketData => wraped_params,
-            // 需要签名
-            CheckType::Trade | CheckType::UserData => {
-                wraped_params.timestamp = Some(timestamp());
-                wraped_params.recv_window = Some(5000);
-
-                let mut query = serde_urlencoded::to_string(&wraped_params).unwrap();
-                // 根据采用的算法不同，signature可能会比较长
-                query.reserve(1000);
-
-                let signature = api_sec_key.signature(&query).unwrap();
-                wraped_params.signature = Some(signature.signature);
-                wraped_params
+        async move {
+            // rest api的api key总是在http header中设置，且签名不需要api_key也作为payload的一部分，所以这里不设置api key
+            let mut wraped_params = PWrapperParams {
+                api_key: None,
+                signature: None,
+                timestamp: None,
+                recv_window: None,
+                params: self,
+            };
+            match self.check_type() {
+                // 不需要签名的
+                CheckType::None | CheckType::UserStream | CheckType::MarketData => {
+                    Ok(wraped_params)
+                }
+                // 需要签名
+                CheckType::Trade | CheckType::UserData => {
+                    wraped_params.timestamp = Some(opts.timestamp());
+                    wraped_params.recv_window =
+                        Some(self.recv_window().unwrap_or(opts.recv_window));
+
+                    let mut query = serde_urlencoded::to_string(&wraped_params).unwrap();
+                    // 根据采用的算法不同，signature可能会比较长
+                    query.reserve(1000);
+
+                    wraped_params.signature = Some(signer.sign(&query).await?);
+                    Ok(wraped_params)
+                }
             }
         }
     }
@@ -109,13 +255,20 @@ impl<'a, T> PWebSocketApi<'a, T>
 where
     T: Serialize + Param,
 {
-    pub fn new(api_sec_key: &'a ApiSecKey, method: &'static str, params: Option<&'a T>) -> Self {
+    /// api_key来自api_sec_key，签名则通过signer完成
+    pub async fn new(
+        api_sec_key: &'a ApiSecKey,
+        signer: &dyn Signer,
+        method: &'static str,
+        params: Option<&'a T>,
+        opts: &SignOptions,
+    ) -> BiAnResult<Self> {
         if params.is_none() {
-            return Self {
+            return Ok(Self {
                 id: Uuid::new_v4(),
                 method,
                 params: None,
-            };
+            });
         }
         let mut wrap_params = PWrapperParams {
             params: params.unwrap(),
@@ -124,17 +277,17 @@ where
             timestamp: None,
             signature: None,
         };
-        wrap_params.payload(api_sec_key);
+        wrap_params.payload(api_sec_key, signer, opts).await?;
 
-        Self {
+        Ok(Self {
             id: Uuid::new_v4(),
             method,
             params: Some(wrap_params),
-        }
+        })
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Serialize)]
 pub struct PWrapperParams<'a, T>
 where
     T: Serialize + Param,
@@ -146,18 +299,39 @@ where
     #[serde(rename = "apiKey", skip_serializing_if = "Option::is_none")]
     api_key: Option<&'a str>,
     #[serde(rename = "recvWindow", skip_serializing_if = "Option::is_none")]
-    recv_window: Option<u32>,
+    recv_window: Option<RecvWindow>,
     #[serde(skip_serializing_if = "Option::is_none")]
     timestamp: Option<u128>,
     #[serde(skip_serializing_if = "Option::is_none")]
     signature: Option<String>,
 }
 
+/// Debug时隐藏apiKey和signature
+impl<T> Debug for PWrapperParams<'_, T>
+where
+    T: Serialize + Param + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PWrapperParams")
+            .field("params", &self.params)
+            .field("api_key", &redact(&self.api_key))
+            .field("recv_window", &self.recv_window)
+            .field("timestamp", &self.timestamp)
+            .field("signature", &redact(&self.signature))
+            .finish()
+    }
+}
+
 impl<'a, T> PWrapperParams<'a, T>
 where
     T: Serialize + Param,
 {
-    fn payload(&mut self, api_sec_key: &'a ApiSecKey) {
+    async fn payload(
+        &mut self,
+        api_sec_key: &'a ApiSecKey,
+        signer: &dyn Signer,
+        opts: &SignOptions,
+    ) -> BiAnResult<()> {
         match self.params.check_type() {
             // 不需要API
             CheckType::None => {}
@@ -168,17 +342,17 @@ where
             // 需要签名，且签名时需要将api_key纳入签名的payload
             CheckType::Trade | CheckType::UserData => {
                 self.api_key = api_sec_key.api_key();
-                self.timestamp = Some(timestamp());
-                self.recv_window = Some(5000);
+                self.timestamp = Some(opts.timestamp());
+                self.recv_window = Some(self.params.recv_window().unwrap_or(opts.recv_window));
 
                 let mut query = serde_urlencoded::to_string(&self).unwrap();
                 // 根据采用的算法不同，signature可能会比较长
                 query.reserve(1000);
 
-                let signature = api_sec_key.signature(&query).unwrap();
-                self.signature = Some(signature.signature);
+                self.signature = Some(signer.sign(&query).await?);
             }
         }
+        Ok(())
     }
 }
 
@@ -190,6 +364,11 @@ impl PPing {
     }
 }
 impl Param for PPing {}
+impl Endpoint for PPing {
+    type Response = serde_json::Value;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/ping";
+}
 
 #[derive(Debug, Serialize)]
 pub struct PServerTime {}
@@ -199,69 +378,84 @@ impl PServerTime {
     }
 }
 impl Param for PServerTime {}
+impl Endpoint for PServerTime {
+    type Response = ServerTime;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/time";
+}
 
 #[derive(Debug)]
-pub struct PExchangeInfo<'a> {
-    // symbols: Option<Vec<&'a str>>,
-    // permissions 不提供该字段时，默认包含["MARGIN", "SPOT"]，因此省略该字段，并且不能同时与symbols字段使用
-    permissions: Vec<&'a str>,
+pub struct PExchangeInfo {
+    symbols: Vec<String>,
+    // permissions 不提供该字段时，默认包含["MARGIN", "SPOT"]，不能与symbols字段同时使用
+    permissions: Vec<String>,
     show_permission_sets: bool,
     // symbolStatus 该字段不能和symbols字段同时使用
-    // symbol_status: String
+    symbol_status: Option<String>,
 }
-impl<'a> PExchangeInfo<'a> {
-    pub fn new(permission: Permission) -> PExchangeInfo<'a> {
-        let permission_str = match permission {
-            Permission::Spot => "SPOT",
-            _ => {
-                panic!(
-                    "wrong argument `{permission:?}`, exchange_info only support SPOT Permission"
-                );
-            }
-        };
-        PExchangeInfo {
-            // symbols,
-            permissions: vec![permission_str],
-            show_permission_sets: false,
+impl PExchangeInfo {
+    pub fn new(permission: Permission) -> PExchangeInfo {
+        Self::from_query(&ExchangeInfoQuery::new(vec![permission]))
+            .expect("single permission query is always valid")
+    }
+
+    /// 根据查询范围生成请求参数
+    ///
+    /// 只查询单个权限时，关闭响应中permissionSets的显示以减小响应体积，
+    /// 其它情况下都需要显示permissionSets，以便区分各交易对的权限
+    pub fn from_query(query: &ExchangeInfoQuery) -> BiAnResult<PExchangeInfo> {
+        if !query.symbols.is_empty()
+            && (!query.permissions.is_empty() || query.symbol_status.is_some())
+        {
+            return Err(BiAnApiError::ArgumentError(
+                "symbols can't be used together with permissions or symbol_status".into(),
+            ));
+        }
+        if query.symbols.is_empty() && query.permissions.is_empty() {
+            return Err(BiAnApiError::ArgumentError(
+                "one of symbols and permissions must be provided".into(),
+            ));
         }
+
+        Ok(PExchangeInfo {
+            symbols: query.symbols.clone(),
+            permissions: query.permission_strs(),
+            show_permission_sets: query.permissions.len() != 1,
+            symbol_status: query.symbol_status.clone(),
+        })
     }
 }
-impl Param for PExchangeInfo<'_> {}
+impl Param for PExchangeInfo {}
+impl Endpoint for PExchangeInfo {
+    type Response = ExchangeInfo;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/exchangeInfo";
+}
 
-impl Serialize for PExchangeInfo<'_> {
+impl Serialize for PExchangeInfo {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("PExchangeInfo", 2)?;
+        let mut state = serializer.serialize_struct("PExchangeInfo", 4)?;
 
         // Serialize `symbols` as `["a","b","c"]` format
-        // if let Some(ref symbols) = self.symbols {
-        //     if !symbols.is_empty() {
-        //         let ele = symbols
-        //             .iter()
-        //             .map(|s| format!(r#""{}""#, s))
-        //             .collect::<Vec<_>>();
-        //         let serialized_symbols = format!("[{}]", ele.join(","));
-        //         state.serialize_field("symbols", &serialized_symbols)?;
-        //     }
-        // }
+        if let Some(symbols) = list_2_str(self.symbols.iter().map(String::as_str).collect()) {
+            state.serialize_field("symbols", &symbols)?;
+        }
 
         // Serialize `permissions` as `["a","b","c"]` format
-        let ele = self
-            .permissions
-            .iter()
-            .map(|p| format!(r#""{p}""#))
-            .collect::<Vec<_>>();
-        let serialized_permissions = format!("[{}]", ele.join(","));
-        state.serialize_field("permissions", &serialized_permissions)?;
+        if let Some(permissions) = list_2_str(self.permissions.iter().map(String::as_str).collect())
+        {
+            state.serialize_field("permissions", &permissions)?;
+        }
 
         // Serialize other fields normally
         state.serialize_field("showPermissionSets", &self.show_permission_sets)?;
 
-        // if let Some(ref symbol_status) = self.symbol_status {
-        //     state.serialize_field("SymbolStatus", symbol_status)?;
-        // }
+        if let Some(ref symbol_status) = self.symbol_status {
+            state.serialize_field("symbolStatus", symbol_status)?;
+        }
 
         state.end()
     }
@@ -270,6 +464,7 @@ impl Serialize for PExchangeInfo<'_> {
 #[cfg(test)]
 mod test {
     use super::PExchangeInfo;
+    use crate::client::exchange_info::ExchangeInfoQuery;
     use serde_urlencoded;
     #[test]
     fn test_p_exchange_info() {
@@ -282,6 +477,29 @@ mod test {
             "None test failed"
         );
 
+        // test symbols with permissions
+        let q = ExchangeInfoQuery::new(vec![
+            ba_types::Permission::Spot,
+            ba_types::Permission::Margin,
+        ])
+        .symbol_status("trading");
+        let x = serde_urlencoded::to_string(PExchangeInfo::from_query(&q).unwrap());
+        assert_eq!(
+            Ok("permissions=%5B%22SPOT%22%2C%22MARGIN%22%5D&showPermissionSets=true&symbolStatus=TRADING".to_string()),
+            x,
+            "permissions with symbol status test failed"
+        );
+
+        // test symbols
+        let q = ExchangeInfoQuery::symbols(["bnbbtc", "BTCUSDT"]);
+        let x = serde_urlencoded::to_string(PExchangeInfo::from_query(&q).unwrap());
+        assert_eq!(
+            Ok("symbols=%5B%22BNBBTC%22%2C%22BTCUSDT%22%5D&showPermissionSets=true".to_string()),
+            x,
+            "symbols test failed"
+        );
+        assert!(PExchangeInfo::from_query(&q.symbol_status("TRADING")).is_err());
+
         // test empty Vec
         // p = PExchangeInfo::new(Some(vec![]));
         // x = serde_urlencoded::to_string(&p);
@@ -322,6 +540,15 @@ impl PDepth<'_> {
     }
 }
 impl Param for PDepth<'_> {}
+impl Endpoint for PDepth<'_> {
+    type Response = Depth;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/depth";
+    /// limit，不指定时为默认值100
+    fn weight_arg(&self) -> Option<u32> {
+        Some(self.limit.unwrap_or(100) as u32)
+    }
+}
 
 #[derive(Debug, Serialize)]
 pub struct PTrades<'a> {
@@ -343,6 +570,11 @@ impl PTrades<'_> {
     }
 }
 impl Param for PTrades<'_> {}
+impl Endpoint for PTrades<'_> {
+    type Response = Vec<Trade>;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/trades";
+}
 
 #[derive(Debug, Serialize)]
 pub struct PHistoricalTrades<'a> {
@@ -373,6 +605,11 @@ impl PHistoricalTrades<'_> {
     }
 }
 impl Param for PHistoricalTrades<'_> {}
+impl Endpoint for PHistoricalTrades<'_> {
+    type Response = Vec<HistoricalTrade>;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/historicalTrades";
+}
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -432,6 +669,11 @@ impl PAggTrades<'_> {
     }
 }
 impl Param for PAggTrades<'_> {}
+impl Endpoint for PAggTrades<'_> {
+    type Response = Vec<AggTrade>;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/aggTrades";
+}
 
 /// 获取K线数据的请求参数
 #[derive(Debug, Serialize)]
@@ -477,6 +719,11 @@ impl PKLine {
     }
 }
 impl Param for PKLine {}
+impl Endpoint for PKLine {
+    type Response = KLines;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/klines";
+}
 
 #[derive(Debug, Serialize)]
 pub struct PAvgPrice {
@@ -490,6 +737,11 @@ impl PAvgPrice {
     }
 }
 impl Param for PAvgPrice {}
+impl Endpoint for PAvgPrice {
+    type Response = AvgPrice;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/avgPrice";
+}
 
 #[derive(Debug, Serialize)]
 pub struct PPrice {
@@ -512,6 +764,15 @@ impl PPrice {
     }
 }
 impl Param for PPrice {}
+impl Endpoint for PPrice {
+    type Response = Prices;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/ticker/price";
+    /// 交易对的个数，不指定交易对时为None
+    fn weight_arg(&self) -> Option<u32> {
+        symbol_arg(&self.symbol, &self.symbols)
+    }
+}
 
 #[derive(Debug, Serialize)]
 pub struct PBookTicker {
@@ -534,6 +795,15 @@ impl PBookTicker {
     }
 }
 impl Param for PBookTicker {}
+impl Endpoint for PBookTicker {
+    type Response = BookTickers;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/ticker/bookTicker";
+    /// 交易对的个数，不指定交易对时为None
+    fn weight_arg(&self) -> Option<u32> {
+        symbol_arg(&self.symbol, &self.symbols)
+    }
+}
 
 #[derive(Debug, Serialize)]
 pub struct PHr24 {
@@ -558,6 +828,15 @@ impl PHr24 {
     }
 }
 impl Param for PHr24 {}
+impl Endpoint for PHr24 {
+    type Response = FullTickers;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/ticker/24hr";
+    /// 交易对的个数，不指定交易对时为None
+    fn weight_arg(&self) -> Option<u32> {
+        symbol_arg(&self.symbol, &self.symbols)
+    }
+}
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -645,12 +924,22 @@ impl POrder {
             qty: qty.map(|x| x.to_string()),
             quote_order_qty: quote_order_qty.map(|x| x.to_string()),
             price: price.map(|x| x.to_string()),
-            new_client_order_id: new_client_order_id.map(String::from),
+            // 总是带上newClientOrderId，下单结果未知时可以据此查询订单
+            new_client_order_id: Some(
+                new_client_order_id
+                    .map(String::from)
+                    .unwrap_or_else(|| Uuid::new_v4().simple().to_string()),
+            ),
             stop_price: stop_price.map(|x| x.to_string()),
             iceberg_qty: iceberg_qty.map(|x| x.to_string()),
             new_order_resp_type: new_order_resp_type.map(OrderRespType::from),
         })
     }
+
+    /// 订单的clientOrderId，未指定时为自动生成的值
+    pub fn client_order_id(&self) -> &str {
+        self.new_client_order_id.as_deref().unwrap_or_default()
+    }
 }
 impl Param for POrder {
     fn check_type(&self) -> CheckType {
@@ -661,6 +950,11 @@ impl Param for POrder {
         PRateLimit::ApiUid
     }
 }
+impl Endpoint for POrder {
+    type Response = Order;
+    const METHOD: RestMethod = RestMethod::Post;
+    const PATH: &'static str = "/api/v3/order";
+}
 
 /// 撤销订单
 #[derive(Debug, Serialize)]
@@ -699,6 +993,11 @@ impl Param for PCancelOrder {
         CheckType::Trade
     }
 }
+impl Endpoint for PCancelOrder {
+    type Response = CancelOrderInfo;
+    const METHOD: RestMethod = RestMethod::Delete;
+    const PATH: &'static str = "/api/v3/order";
+}
 
 /// 撤销单一交易对的所有挂单
 #[derive(Debug, Serialize)]
@@ -717,6 +1016,11 @@ impl Param for PCancelOpenOrders {
         CheckType::Trade
     }
 }
+impl Endpoint for PCancelOpenOrders {
+    type Response = Vec<CancelOpenOrdersInfo>;
+    const METHOD: RestMethod = RestMethod::Delete;
+    const PATH: &'static str = "/api/v3/openOrders";
+}
 
 /// 查询订单
 #[derive(Debug, Serialize)]
@@ -749,6 +1053,11 @@ impl Param for PGetOrder {
         CheckType::UserData
     }
 }
+impl Endpoint for PGetOrder {
+    type Response = OrderInfo;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/order";
+}
 
 /// 当前挂单
 #[derive(Debug, Serialize)]
@@ -765,6 +1074,15 @@ impl Param for PGetOpenOrders {
         CheckType::UserData
     }
 }
+impl Endpoint for PGetOpenOrders {
+    type Response = Vec<OrderInfo>;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/openOrders";
+    /// 指定了交易对时为1，不指定时查询所有交易对的挂单
+    fn weight_arg(&self) -> Option<u32> {
+        self.symbol.as_ref().map(|_| 1)
+    }
+}
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -797,6 +1115,11 @@ impl Param for PAllOrders {
         CheckType::UserData
     }
 }
+impl Endpoint for PAllOrders {
+    type Response = Vec<OrderInfo>;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/allOrders";
+}
 
 /// 现货交易对下架计划
 #[derive(Debug, Serialize)]
@@ -811,6 +1134,11 @@ impl Param for PDelist {
         CheckType::MarketData
     }
 }
+impl Endpoint for PDelist {
+    type Response = DelistSchedule;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/sapi/v1/spot/delist-schedule";
+}
 
 /// 现货交易对下架计划
 #[derive(Debug, Serialize)]
@@ -825,6 +1153,11 @@ impl Param for PCapital {
         CheckType::UserData
     }
 }
+impl Endpoint for PCapital {
+    type Response = serde_json::Value;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/sapi/v1/capital/config/getall";
+}
 
 /// 账户信息
 #[derive(Debug, Serialize)]
@@ -842,6 +1175,11 @@ impl Param for PAccount {
         CheckType::UserData
     }
 }
+impl Endpoint for PAccount {
+    type Response = Account;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/account";
+}
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -877,6 +1215,15 @@ impl Param for PMyTrades {
         CheckType::UserData
     }
 }
+impl Endpoint for PMyTrades {
+    type Response = Vec<MyTrades>;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/myTrades";
+    /// 指定了orderId时为1
+    fn weight_arg(&self) -> Option<u32> {
+        self.order_id.map(|_| 1)
+    }
+}
 
 #[derive(Debug, Serialize)]
 pub struct PRateLimitInfo {}
@@ -890,13 +1237,27 @@ impl Param for PRateLimitInfo {
         CheckType::Trade
     }
 }
+impl Endpoint for PRateLimitInfo {
+    type Response = Vec<RateLimit>;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/api/v3/rateLimit/order";
+}
 
-#[derive(Debug, Serialize)]
+#[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PListenKey {
     listen_key: Option<String>,
     symbol: Option<String>,
 }
+/// Debug时隐藏listenKey
+impl Debug for PListenKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PListenKey")
+            .field("listen_key", &redact(&self.listen_key))
+            .field("symbol", &self.symbol)
+            .finish()
+    }
+}
 impl PListenKey {
     pub fn new(listen_key: Option<&str>, symbol: Option<&str>) -> Self {
         Self {
@@ -924,6 +1285,13 @@ impl Param for PDustBtc {
         CheckType::UserData
     }
 }
+impl Endpoint for PDustBtc {
+    type Response = DustBtc;
+    const METHOD: RestMethod = RestMethod::Post;
+    const PATH: &'static str = "/sapi/v1/asset/dust-btc";
+    // 虽然是POST请求，但只是查询
+    const IDEMPOTENT: bool = true;
+}
 
 #[derive(Debug)]
 pub struct PDust {
@@ -963,6 +1331,11 @@ impl Param for PDust {
         PRateLimit::ApiUid
     }
 }
+impl Endpoint for PDust {
+    type Response = Dust;
+    const METHOD: RestMethod = RestMethod::Post;
+    const PATH: &'static str = "/sapi/v1/asset/dust";
+}
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -984,6 +1357,11 @@ impl Param for PSubAccountList<'_> {
         PRateLimit::ApiIp
     }
 }
+impl Endpoint for PSubAccountList<'_> {
+    type Response = SubAccounts;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/sapi/v1/sub-account/list";
+}
 
 #[derive(Debug, Serialize)]
 pub struct PSubAccountAssets<'a> {
@@ -1003,6 +1381,11 @@ impl Param for PSubAccountAssets<'_> {
         PRateLimit::ApiUid
     }
 }
+impl Endpoint for PSubAccountAssets<'_> {
+    type Response = SubAccountBalances;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/sapi/v3/sub-account/assets";
+}
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -1050,6 +1433,11 @@ impl Param for PSubAccountUniversalTransfer<'_> {
         PRateLimit::ApiUid
     }
 }
+impl Endpoint for PSubAccountUniversalTransfer<'_> {
+    type Response = UniversalTransfer;
+    const METHOD: RestMethod = RestMethod::Post;
+    const PATH: &'static str = "/sapi/v1/sub-account/universalTransfer";
+}
 
 #[derive(Debug, Serialize)]
 pub struct PAccountInfo {}
@@ -1066,6 +1454,11 @@ impl Param for PAccountInfo {
         PRateLimit::ApiUid
     }
 }
+impl Endpoint for PAccountInfo {
+    type Response = AccountInfo;
+    const METHOD: RestMethod = RestMethod::Get;
+    const PATH: &'static str = "/sapi/v1/account/info";
+}
 
 #[derive(Debug, Serialize)]
 pub struct PSessionLogon {}
@@ -1140,4 +1533,51 @@ mod tt {
         };
         println!("{:?}", serde_urlencoded::to_string(&d));
     }
+
+    #[test]
+    fn test_recv_window() {
+        use super::RecvWindow;
+        use std::time::Duration;
+
+        #[derive(Serialize)]
+        struct P {
+            #[serde(rename = "recvWindow")]
+            recv_window: RecvWindow,
+        }
+        let query = |x: RecvWindow| serde_urlencoded::to_string(P { recv_window: x }).unwrap();
+
+        assert_eq!(query(RecvWindow::default()), "recvWindow=5000");
+        assert_eq!(query(RecvWindow::micros(6_000_346)), "recvWindow=6000.346");
+        assert_eq!(query(RecvWindow::micros(1_500)), "recvWindow=1.5");
+        assert_eq!(query(Duration::from_secs(60).into()), "recvWindow=60000");
+        assert_eq!(RecvWindow::from(60_000), RecvWindow::MAX);
+        // 超出最大值时取最大值
+        assert_eq!(RecvWindow::millis(70_000), RecvWindow::MAX);
+        assert_eq!(RecvWindow::micros(u64::MAX), RecvWindow::MAX);
+        assert_eq!(RecvWindow::from(Duration::MAX), RecvWindow::MAX);
+        assert_eq!(query(RecvWindow::from(120_000)), "recvWindow=60000");
+    }
+
+    #[test]
+    fn test_endpoint_weight() {
+        use super::{Endpoint, PDepth, PGetOpenOrders, PHr24, PMyTrades, PPrice};
+        use crate::client::RateLimitParam;
+
+        let w = |x: RateLimitParam| match x {
+            RateLimitParam::Weight(n) => n,
+            _ => unreachable!(),
+        };
+        // limit默认为100
+        assert_eq!(w(PDepth::new("BTCUSDT", None).unwrap().weight()), 5);
+        assert_eq!(w(PDepth::new("BTCUSDT", Some(1000)).unwrap().weight()), 50);
+        assert_eq!(w(PPrice::new(vec!["BTCUSDT"]).weight()), 2);
+        assert_eq!(w(PPrice::new(vec![]).weight()), 4);
+        assert_eq!(w(PHr24::new(vec!["BTCUSDT", "ETHUSDT"]).weight()), 2);
+        assert_eq!(w(PHr24::new(vec![]).weight()), 80);
+        assert_eq!(w(PGetOpenOrders::new(Some("BTCUSDT".into())).weight()), 6);
+        assert_eq!(w(PGetOpenOrders::new(None).weight()), 80);
+        let my_trades = |order_id| PMyTrades::new("BTCUSDT", order_id, None, None, None, None);
+        assert_eq!(w(my_trades(Some(1)).weight()), 5);
+        assert_eq!(w(my_trades(None).weight()), 20);
+    }
 }
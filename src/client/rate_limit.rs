@@ -22,15 +22,137 @@
 //!    "limit": 61000
 //!    }
 //! ]
+//!
+//! 每条规则对应一个限速，按各自的时间段(按UTC对齐)重置，
+//! 并通过`x-mbx-used-weight-<n><unit>`、`x-mbx-order-count-<n><unit>`响应头更新已使用的值
 
+#[cfg(feature = "shared-rate-limit")]
+use super::shared_limit::{SharedBudget, SharedKind, SharedSlot};
+use super::{clock::Clock, params::PRateLimit};
+use crate::errors::{BiAnApiError, BiAnResult};
 use ba_types::{ExchangeInfo, RateLimit, RateLimitInterVal, RateLimitType};
-use chrono_ext::{DateTime, FixedOffset, ParseDateTimeExt, Timelike, now0};
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::error;
+use chrono_ext::ParseDateTimeExt;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::{RwLock, broadcast, oneshot};
+use tracing::{error, warn};
+
+/// 收到418或429但响应中没有Retry-After时，默认的封禁时长
+const DEFAULT_BAN_DURATION: Duration = Duration::from_secs(60);
+
+/// 两次tick之间的最大间隔，使`update()`新增的限速也能被及时重置
+const MAX_TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// RestConn自身的api_sec_key(不属于KeyPool)对应的账户
+pub(crate) const DEFAULT_UID: &str = "";
+
+/// /sapi/*接口每分钟的IP权重上限
+const SAPI_IP_WEIGHT_LIMIT: u32 = 12000;
+/// /sapi/*接口每个账户每分钟的UID权重上限
+const SAPI_UID_WEIGHT_LIMIT: u32 = 180000;
+
+/// 限速相关的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum RateLimitEvent {
+    /// 收到418(IP被封禁)或429(请求过多)，在until之前的所有请求都将被暂停
+    Banned { status: u16, until: SystemTime },
+    /// 某个限速已使用的比例达到了阈值，每个时间段内每个阈值最多触发一次，
+    /// 同时越过多个阈值时只报告最高的一个
+    ThresholdCrossed {
+        bucket: BucketSnapshot,
+        threshold: f64,
+    },
+}
+
+/// 限速的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketKind {
+    /// /api/*接口按IP计数的权重
+    Weight,
+    /// /api/*接口按IP计数的请求次数
+    RawRequests,
+    /// 每个账户的下单次数
+    Orders,
+    /// /sapi/*接口按IP计数的权重
+    SapiIpWeight,
+    /// /sapi/*接口每个账户的权重
+    SapiUidWeight,
+}
+
+/// 某个限速的当前状态
+#[derive(Debug, Clone, PartialEq)]
+pub struct BucketSnapshot {
+    pub kind: BucketKind,
+    pub interval: Interval,
+    /// 所属的账户(KeyPool中key的名称，RestConn自身的key为空字符串)，
+    /// 只有下单次数和/sapi/*接口的UID权重按账户计数
+    pub uid: Option<String>,
+    pub limit: u32,
+    /// 当前时间段内剩余的值
+    pub remain: u32,
+    /// 当前时间段结束、剩余值被重置的时间
+    pub reset_at: SystemTime,
+}
+
+impl BucketSnapshot {
+    /// 已使用的比例
+    pub fn used_ratio(&self) -> f64 {
+        used_ratio(self.limit, self.remain)
+    }
+}
+
+fn used_ratio(limit: u32, remain: u32) -> f64 {
+    if limit == 0 {
+        return 1.0;
+    }
+    limit.saturating_sub(remain) as f64 / limit as f64
+}
+
+/// 限速的使用率阈值，已使用的比例达到ratio时发送`RateLimitEvent::ThresholdCrossed`
+///
+/// ```rust
+/// let rest_conn = RestConn::builder(api_sec_key)
+///     .rate_limit_thresholds([
+///         Threshold::new(BucketKind::Weight, 0.8),
+///         Threshold::new(BucketKind::Orders, 0.95).interval(Interval::parse("1d").unwrap()),
+///     ])
+///     .build()
+///     .await?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    pub kind: BucketKind,
+    /// 只匹配该时间段的限速，None时匹配该类型的所有限速
+    pub interval: Option<Interval>,
+    /// 已使用的比例，0.0 ~ 1.0
+    pub ratio: f64,
+}
+
+impl Threshold {
+    pub fn new(kind: BucketKind, ratio: f64) -> Self {
+        Self {
+            kind,
+            interval: None,
+            ratio,
+        }
+    }
+
+    /// 只匹配该时间段的限速
+    pub fn interval(mut self, interval: Interval) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    fn matches(&self, kind: BucketKind, interval: Interval) -> bool {
+        self.kind == kind && self.interval.is_none_or(|x| x == interval)
+    }
+}
 
 /// 发送的请求属于哪种类型的限速
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum RateLimitParam {
     /// 该请求不是下单操作，参数值表示该请求所需权重值
     Weight(u32),
@@ -42,79 +164,1105 @@ pub enum RateLimitParam {
     Both(u32),
 }
 
+impl RateLimitParam {
+    /// 所需权重值，以及是否为下单操作
+    fn need(&self) -> (u32, bool) {
+        match *self {
+            RateLimitParam::Weight(n) => (n, false),
+            RateLimitParam::Order(n) => (n, true),
+            RateLimitParam::Both(n) => (n, true),
+        }
+    }
+}
+
+/// 限速分区的份额，只对/api/*接口的权重和下单次数生效
+///
+/// 分区只能使用自己的份额，未分区的请求只能使用所有分区的份额之外的部分，
+/// 因此分区内的请求不会被其它请求耗尽权重，也不会耗尽其它请求的权重
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quota {
+    /// 占每个限速上限的比例，0.0 ~ 1.0
+    Share(f64),
+    /// 最短时间段内的权重值和下单次数(例如每分钟1000权重、每10秒20次下单)，
+    /// 更长的时间段按相同的比例换算
+    Fixed { weight: u32, orders: u32 },
+}
+
+impl Quota {
+    /// 占该类型限速上限的比例，shortest为该类型中最短时间段的限速上限
+    fn ratio(&self, kind: BucketKind, shortest: u32) -> f64 {
+        match *self {
+            Quota::Share(x) => x,
+            Quota::Fixed { weight, orders } => {
+                let n = if kind == BucketKind::Orders {
+                    orders
+                } else {
+                    weight
+                };
+                n as f64 / shortest.max(1) as f64
+            }
+        }
+    }
+
+    /// 在上限为limit的限速中所占的值，shortest为该类型中最短时间段的限速上限
+    fn of(&self, kind: BucketKind, limit: u32, shortest: u32) -> u32 {
+        (limit as f64 * self.ratio(kind, shortest).clamp(0.0, 1.0)) as u32
+    }
+}
+
+/// 限速分区的使用统计
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScopeStats {
+    pub name: String,
+    pub quota: Quota,
+    /// 累计获取到权重的请求数
+    pub requests: u64,
+    /// 累计使用的权重
+    pub weight: u64,
+    /// 累计的下单次数
+    pub orders: u64,
+    /// 当前时间段内分区的权重和下单次数，limit为分区的份额
+    pub buckets: Vec<BucketSnapshot>,
+}
+
+/// 分区在某个限速的当前时间段内已使用的值
+struct ScopeUsage {
+    kind: BucketKind,
+    /// 下单次数按账户计数，权重为None
+    uid: Option<String>,
+    interval: Interval,
+    window_start: i64,
+    used: u32,
+}
+
+/// 限速分区，参考`RestConn::scoped()`
+struct Scope {
+    quota: Quota,
+    usage: Vec<ScopeUsage>,
+    requests: u64,
+    weight: u64,
+    orders: u64,
+}
+
+impl Scope {
+    fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            usage: vec![],
+            requests: 0,
+            weight: 0,
+            orders: 0,
+        }
+    }
+
+    /// 当前时间段内已使用的值，进入新的时间段时清零
+    fn used(
+        &mut self,
+        kind: BucketKind,
+        uid: Option<&str>,
+        interval: Interval,
+        now_ms: i64,
+    ) -> &mut u32 {
+        let window_start = interval.window_start(now_ms);
+        let idx = match self
+            .usage
+            .iter()
+            .position(|x| x.kind == kind && x.uid.as_deref() == uid && x.interval == interval)
+        {
+            Some(idx) => idx,
+            None => {
+                self.usage.push(ScopeUsage {
+                    kind,
+                    uid: uid.map(String::from),
+                    interval,
+                    window_start,
+                    used: 0,
+                });
+                self.usage.len() - 1
+            }
+        };
+        let x = &mut self.usage[idx];
+        if window_start > x.window_start {
+            x.window_start = window_start;
+            x.used = 0;
+        }
+        &mut x.used
+    }
+
+    /// 在buckets(同一类型，按时间段从短到长排列)中再使用n是否超出份额
+    fn allows(
+        &mut self,
+        kind: BucketKind,
+        uid: Option<&str>,
+        buckets: &[(Interval, u32)],
+        n: u32,
+        now_ms: i64,
+    ) -> bool {
+        let shortest = buckets.first().map(|x| x.1).unwrap_or_default();
+        buckets.iter().all(|&(interval, limit)| {
+            let quota = self.quota.of(kind, limit, shortest);
+            *self.used(kind, uid, interval, now_ms) + n <= quota
+        })
+    }
+
+    /// 在buckets中为分区预留但尚未使用的值
+    fn unused(
+        &mut self,
+        kind: BucketKind,
+        uid: Option<&str>,
+        buckets: &[(Interval, u32)],
+        now_ms: i64,
+    ) -> Vec<u32> {
+        let shortest = buckets.first().map(|x| x.1).unwrap_or_default();
+        buckets
+            .iter()
+            .map(|&(interval, limit)| {
+                let quota = self.quota.of(kind, limit, shortest);
+                quota.saturating_sub(*self.used(kind, uid, interval, now_ms))
+            })
+            .collect()
+    }
+
+    fn add(
+        &mut self,
+        kind: BucketKind,
+        uid: Option<&str>,
+        buckets: &[(Interval, u32)],
+        n: u32,
+        refund: bool,
+        now_ms: i64,
+    ) {
+        for &(interval, _) in buckets {
+            let used = self.used(kind, uid, interval, now_ms);
+            *used = if refund {
+                used.saturating_sub(n)
+            } else {
+                used.saturating_add(n)
+            };
+        }
+    }
+}
+
+/// 请求消耗哪一组限速
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LimitBucket {
+    /// /api/*接口，消耗IP的权重和请求次数，下单请求还消耗账户的下单次数
+    Api,
+    /// /sapi/*接口中按IP计数的，消耗SAPI的IP权重
+    SapiIp,
+    /// /sapi/*接口中按UID计数的，消耗账户的SAPI UID权重
+    SapiUid,
+}
+
+impl LimitBucket {
+    /// 根据请求路径和`Param::rate_limit()`确定，其它路径的请求不做限速
+    pub(crate) fn of(path: &str, rate_limit: PRateLimit) -> Option<Self> {
+        if path.starts_with("/api") {
+            return Some(Self::Api);
+        }
+        if !path.starts_with("/sapi") {
+            return None;
+        }
+        match rate_limit {
+            PRateLimit::ApiIp => Some(Self::SapiIp),
+            PRateLimit::ApiUid => Some(Self::SapiUid),
+        }
+    }
+}
+
+/// 响应头中当前限速时间段内已经使用的值
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UsedPermits {
+    /// x-mbx-used-weight-<n><unit>
+    pub weight: Vec<(Interval, u32)>,
+    /// x-mbx-order-count-<n><unit>
+    pub order_count: Vec<(Interval, u32)>,
+    /// x-sapi-used-ip-weight-1m
+    pub sapi_ip_1m: Option<u32>,
+    /// x-sapi-used-uid-weight-1m
+    pub sapi_uid_1m: Option<u32>,
+}
+
+impl UsedPermits {
+    /// 从响应头中的限速计数器解析，key为小写的响应头名称
+    pub(crate) fn from_counters(counters: &HashMap<String, u32>) -> Self {
+        let mut used = Self {
+            sapi_ip_1m: counters.get("x-sapi-used-ip-weight-1m").copied(),
+            sapi_uid_1m: counters.get("x-sapi-used-uid-weight-1m").copied(),
+            ..Default::default()
+        };
+        for (k, &n) in counters {
+            if let Some(x) = k
+                .strip_prefix("x-mbx-used-weight-")
+                .and_then(Interval::parse)
+            {
+                used.weight.push((x, n));
+            } else if let Some(x) = k
+                .strip_prefix("x-mbx-order-count-")
+                .and_then(Interval::parse)
+            {
+                used.order_count.push((x, n));
+            }
+        }
+        used
+    }
+}
+
+/// 限速时间段的单位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IntervalUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+}
+
+impl IntervalUnit {
+    fn secs(self) -> i64 {
+        match self {
+            IntervalUnit::Second => 1,
+            IntervalUnit::Minute => 60,
+            IntervalUnit::Hour => 3600,
+            IntervalUnit::Day => 86400,
+        }
+    }
+}
+
+/// 限速的时间段，例如10秒、1分钟、5分钟、1天，时间段按UTC对齐
+///
+/// ```rust
+/// let x = Interval::parse("10s").unwrap();
+/// assert_eq!(x.to_string(), "10s");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    num: u32,
+    unit: IntervalUnit,
+}
+
+impl Interval {
+    /// 限速规则的时间段，interval_num为0时返回None
+    ///
+    /// ba_types不认识的时间单位(如HOUR)在解析exchangeInfo时已被忽略，见`parse_exchange_info()`
+    fn of(rate_limit: &RateLimit) -> Option<Self> {
+        let unit = match rate_limit.interval {
+            RateLimitInterVal::Second => IntervalUnit::Second,
+            RateLimitInterVal::Minute => IntervalUnit::Minute,
+            RateLimitInterVal::Day => IntervalUnit::Day,
+        };
+        (rate_limit.interval_num > 0).then_some(Self {
+            num: rate_limit.interval_num,
+            unit,
+        })
+    }
+
+    /// 从响应头名称的后缀解析，例如"1m"、"10s"、"1d"
+    pub fn parse(suffix: &str) -> Option<Self> {
+        let (num, unit) = suffix.split_at_checked(suffix.len().checked_sub(1)?)?;
+        let unit = match unit {
+            "s" => IntervalUnit::Second,
+            "m" => IntervalUnit::Minute,
+            "h" => IntervalUnit::Hour,
+            "d" => IntervalUnit::Day,
+            _ => return None,
+        };
+        let num = num.parse().ok().filter(|&n| n > 0)?;
+        Some(Self { num, unit })
+    }
+
+    /// 时间段的长度
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.millis() as u64)
+    }
+
+    /// 时间段的长度(毫秒)
+    fn millis(&self) -> i64 {
+        self.num as i64 * self.unit.secs() * 1000
+    }
+
+    /// 包含epoch_ms的时间段的开始时间(毫秒)
+    fn window_start(&self, epoch_ms: i64) -> i64 {
+        epoch_ms - epoch_ms.rem_euclid(self.millis())
+    }
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit {
+            IntervalUnit::Second => 's',
+            IntervalUnit::Minute => 'm',
+            IntervalUnit::Hour => 'h',
+            IntervalUnit::Day => 'd',
+        };
+        write!(f, "{}{}", self.num, unit)
+    }
+}
+
+/// 请求的优先级，剩余权重不足时，优先级高的请求先获得权重，同一优先级的请求先到先得
+///
+/// 默认下单、撤单等非GET请求为High，其它请求为Normal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RequestPriority {
+    /// 下单、撤单等交易请求
+    High = 0,
+    /// 行情、查询等请求
+    #[default]
+    Normal = 1,
+    /// 后台任务(刷新exchange_info、测量延迟等)
+    Low = 2,
+}
+
+/// 等待权重的请求
+struct Waiter {
+    id: u64,
+    weight: u32,
+    order: bool,
+    bucket: LimitBucket,
+    uid: String,
+    scope: Option<String>,
+    /// 等待期间被封禁时，是否继续等待，否则通知`BiAnApiError::Banned`
+    wait_on_ban: bool,
+    /// 获得权重后通知等待者，永远无法满足时通知错误
+    tx: oneshot::Sender<BiAnResult<()>>,
+}
+
 struct RestApiRateLimitInfo {
     rate_limit: RateLimit,
+    interval: Interval,
     /// 剩余的次数
     remain: u32,
-    /// 限速被重置的时间
-    reset_datetime: DateTime<FixedOffset>,
+    /// 当前时间段的开始时间(epoch毫秒)
+    window_start: i64,
+    /// 当前时间段内已经触发过的最高阈值
+    crossed: f64,
+    /// 多个进程共享的已使用值，设置后remain以共享的值为准
+    #[cfg(feature = "shared-rate-limit")]
+    shared: Option<SharedSlot>,
 }
 
 impl RestApiRateLimitInfo {
-    fn new(rate_limit: RateLimit) -> Self {
-        Self {
+    /// 时间单位不认识的限速规则返回None
+    fn new(rate_limit: RateLimit, now_ms: i64) -> Option<Self> {
+        let interval = Interval::of(&rate_limit)?;
+        Some(Self {
             remain: rate_limit.limit,
+            window_start: interval.window_start(now_ms),
+            crossed: 0.0,
             rate_limit,
-            reset_datetime: now0(),
+            interval,
+            #[cfg(feature = "shared-rate-limit")]
+            shared: None,
+        })
+    }
+
+    /// 扣除n，调用前已经检查过剩余值，reserved为排在前面的等待者预留的部分
+    ///
+    /// 共享时其它进程可能已经用掉了剩余值，在同一个原子操作中再次检查，不足时返回false
+    #[cfg_attr(not(feature = "shared-rate-limit"), allow(unused_variables))]
+    fn take(&mut self, n: u32, reserved: u32, now_ms: i64) -> bool {
+        #[cfg(feature = "shared-rate-limit")]
+        if let Some(slot) = &self.shared {
+            let limit = self.rate_limit.limit.saturating_sub(reserved);
+            let taken = slot.try_add(n, limit, now_ms);
+            let (Ok(used) | Err(used)) = taken;
+            self.remain = self.rate_limit.limit.saturating_sub(used);
+            return taken.is_ok();
         }
+        self.remain -= n;
+        true
     }
 
-    fn reset_permits(&mut self) {
+    /// 退还已经扣除但没有使用的次数
+    #[cfg_attr(not(feature = "shared-rate-limit"), allow(unused_variables))]
+    fn refund(&mut self, n: u32, now_ms: i64) {
+        self.remain = (self.remain + n).min(self.rate_limit.limit);
+        #[cfg(feature = "shared-rate-limit")]
+        if let Some(slot) = &self.shared {
+            let used = slot.sub(n, now_ms);
+            self.remain = self.rate_limit.limit.saturating_sub(used);
+        }
+    }
+
+    fn reset_permits(&mut self, now_ms: i64) {
         self.remain = self.rate_limit.limit;
-        self.reset_datetime = now0();
+        self.window_start = self.interval.window_start(now_ms);
+        self.crossed = 0.0;
+    }
+
+    fn snapshot(&self, kind: BucketKind, uid: Option<&str>) -> BucketSnapshot {
+        BucketSnapshot {
+            kind,
+            interval: self.interval,
+            uid: uid.map(String::from),
+            limit: self.rate_limit.limit,
+            remain: self.remain,
+            reset_at: UNIX_EPOCH
+                + Duration::from_millis((self.window_start + self.interval.millis()) as u64),
+        }
+    }
+
+    /// 进入了新的时间段时重置，共享时同步其它进程已使用的值
+    fn refresh(&mut self, now_ms: i64) {
+        if self.interval.window_start(now_ms) > self.window_start {
+            self.reset_permits(now_ms);
+        }
+        #[cfg(feature = "shared-rate-limit")]
+        if let Some(slot) = &self.shared {
+            self.remain = self.rate_limit.limit.saturating_sub(slot.used(now_ms));
+        }
+    }
+
+    /// now_ms时的剩余值，不修改状态，已经进入新的时间段时为上限
+    fn remain_at(&self, now_ms: i64) -> u32 {
+        if self.interval.window_start(now_ms) > self.window_start {
+            self.rate_limit.limit
+        } else {
+            self.remain
+        }
+    }
+
+    /// 距离当前时间段结束还有多久(毫秒)
+    fn until_reset(&self, now_ms: i64) -> i64 {
+        self.window_start + self.interval.millis() - now_ms
+    }
+
+    /// 根据响应中的已使用值更新剩余值，只有响应发生在当前时间段内才更新
+    #[cfg_attr(not(feature = "shared-rate-limit"), allow(unused_variables))]
+    fn set_used(&mut self, used: Option<u32>, response_ms: i64, now_ms: i64) {
+        if response_ms >= self.window_start
+            && let Some(n) = used
+        {
+            let used = self.rate_limit.limit.saturating_sub(self.remain).max(n);
+            self.remain = self.rate_limit.limit.saturating_sub(used);
+            #[cfg(feature = "shared-rate-limit")]
+            if let Some(slot) = &self.shared {
+                let used = slot.set_max(n, now_ms);
+                self.remain = self.rate_limit.limit.saturating_sub(used);
+            }
+        }
+    }
+
+    /// 按新的限速规则重建，同一时间段的限速保留已使用的值，时间单位不认识的规则被忽略
+    fn rebuild(old: &mut Vec<Self>, rate_limits: &[&RateLimit], now_ms: i64) {
+        let mut new = vec![];
+        for rl in rate_limits {
+            let Some(mut x) = Self::new((*rl).clone(), now_ms) else {
+                warn!("unsupported rate limit interval: {:?}", rl);
+                continue;
+            };
+            if let Some(o) = old.iter().find(|o| o.interval == x.interval) {
+                let used = o.rate_limit.limit.saturating_sub(o.remain);
+                x.remain = x.rate_limit.limit.saturating_sub(used);
+                x.window_start = o.window_start;
+                x.crossed = o.crossed;
+            }
+            new.push(x);
+        }
+        if !new.is_empty() {
+            new.sort_by_key(|x| x.interval.millis());
+            *old = new;
+        }
+    }
+}
+
+/// 从所有限速中扣除n，任一限速不足时退还已扣除的部分并返回false
+fn take_all(infos: &mut [RestApiRateLimitInfo], n: u32, reserved: u32, now_ms: i64) -> bool {
+    let Some(i) = infos.iter_mut().position(|x| !x.take(n, reserved, now_ms)) else {
+        return true;
+    };
+    infos[..i].iter_mut().for_each(|x| x.refund(n, now_ms));
+    false
+}
+
+fn rate_limit(
+    rate_limit_type: RateLimitType,
+    interval: RateLimitInterVal,
+    num: u32,
+    limit: u32,
+) -> RateLimit {
+    RateLimit {
+        rate_limit_type,
+        interval,
+        interval_num: num,
+        limit,
+        count: 0,
+    }
+}
+
+/// 每分钟的权重限速
+fn weight_1m(limit: u32) -> RateLimit {
+    rate_limit(
+        RateLimitType::RequestWeight,
+        RateLimitInterVal::Minute,
+        1,
+        limit,
+    )
+}
+
+/// 排在前面但尚未满足的等待者所预留的权重和次数
+#[derive(Default)]
+struct Reserved {
+    weight: u32,
+    raw_requests: u32,
+    sapi_ip: u32,
+    sapi_uid: HashMap<String, u32>,
+}
+
+impl Reserved {
+    fn add(&mut self, weight: u32, bucket: LimitBucket, uid: &str) {
+        match bucket {
+            LimitBucket::Api => {
+                self.weight = self.weight.saturating_add(weight);
+                self.raw_requests += 1;
+            }
+            LimitBucket::SapiIp => self.sapi_ip = self.sapi_ip.saturating_add(weight),
+            LimitBucket::SapiUid => {
+                let n = self.sapi_uid.entry(uid.to_string()).or_default();
+                *n = n.saturating_add(weight);
+            }
+        }
+    }
+}
+
+/// 单个账户(UID)的限速，每个账户有各自的下单次数和SAPI UID权重，但共享IP的权重
+struct UidLimits {
+    /// 按请求次数计数的下单次数限速，来自exchange_info中的ORDERS规则
+    /// (默认：每10秒100次、每天16W次下单请求)
+    orders: Vec<RestApiRateLimitInfo>,
+    /// /sapi/*接口按UID计数的每分钟的权重限速(默认：每分钟180000权重值)
+    sapi_uid: RestApiRateLimitInfo,
+}
+
+impl UidLimits {
+    fn new(order_limits: &[&RateLimit], now_ms: i64) -> Self {
+        let mut orders = vec![];
+        RestApiRateLimitInfo::rebuild(&mut orders, order_limits, now_ms);
+        Self {
+            orders,
+            sapi_uid: RestApiRateLimitInfo::new(weight_1m(SAPI_UID_WEIGHT_LIMIT), now_ms).unwrap(),
+        }
+    }
+
+    fn refresh(&mut self, now_ms: i64) {
+        self.orders.iter_mut().for_each(|x| x.refresh(now_ms));
+        self.sapi_uid.refresh(now_ms);
     }
 }
 
 struct RestApiRateLimitsInner {
-    /// 按权重计数的每分钟的限速(默认：每分钟6000权重值)
-    weight: RestApiRateLimitInfo,
-    /// 按请求次数计数的每10秒的下单次数限速(默认：每10秒50次下单请求)
-    order_sec10: RestApiRateLimitInfo,
-    /// 按请求次数计数的每天的下单次数限速(默认：每天16W次下单请求)
-    order_day1: RestApiRateLimitInfo,
-    /// 按请求次数计数的每5分钟的请求次数限速(默认：5分钟61000次请求)
-    raw_requests: RestApiRateLimitInfo,
-}
-
-impl Default for RestApiRateLimitsInner {
-    fn default() -> Self {
-        let weight_rl = RateLimit {
-            rate_limit_type: RateLimitType::RequestWeight,
-            interval: RateLimitInterVal::Minute,
-            interval_num: 1,
-            limit: 6000,
-            count: 0,
+    /// 按权重计数的限速，来自REQUEST_WEIGHT规则(默认：每分钟6000权重值)
+    weights: Vec<RestApiRateLimitInfo>,
+    /// 按请求次数计数的限速，来自RAW_REQUESTS规则(默认：5分钟61000次请求)
+    raw_requests: Vec<RestApiRateLimitInfo>,
+    /// /sapi/*接口按IP计数的每分钟的权重限速(默认：每分钟12000权重值)
+    sapi_ip: RestApiRateLimitInfo,
+    /// 每个账户的限速，key为KeyPool中key的名称，RestConn自身的key为`DEFAULT_UID`
+    uids: HashMap<String, UidLimits>,
+    /// 下单次数的限速规则，新账户的下单次数限速使用该规则
+    order_limits: Vec<RateLimit>,
+    /// 多个进程共享的限速预算，只共享按IP计数的限速
+    #[cfg(feature = "shared-rate-limit")]
+    shared: Option<SharedBudget>,
+    /// 限速分区，key为分区的名称
+    scopes: HashMap<String, Scope>,
+    /// 使用率阈值
+    thresholds: Arc<Vec<Threshold>>,
+    events: broadcast::Sender<RateLimitEvent>,
+    /// 等待权重的请求，按优先级分为多个队列，每个队列内先进先出
+    waiters: [VecDeque<Waiter>; 3],
+    next_waiter_id: u64,
+    /// 当前时间的来源
+    clock: Arc<dyn Clock>,
+}
+
+impl RestApiRateLimitsInner {
+    /// 剩余的权重和次数是否足够，reserved为排在前面但尚未满足的请求所预留的
+    fn can_take(
+        &mut self,
+        weight: u32,
+        order: bool,
+        bucket: LimitBucket,
+        uid: &str,
+        scope: Option<&str>,
+        reserved: &Reserved,
+    ) -> bool {
+        match bucket {
+            LimitBucket::Api => {
+                if self
+                    .weights
+                    .iter()
+                    .any(|x| x.remain < weight.saturating_add(reserved.weight))
+                    || self
+                        .raw_requests
+                        .iter()
+                        .any(|x| x.remain < 1 + reserved.raw_requests)
+                {
+                    return false;
+                }
+                if order && self.uid(uid).orders.iter().any(|x| x.remain < 1) {
+                    return false;
+                }
+                self.scope_allows(weight, order, uid, scope)
+            }
+            LimitBucket::SapiIp => self.sapi_ip.remain >= weight.saturating_add(reserved.sapi_ip),
+            LimitBucket::SapiUid => {
+                let reserved = reserved.sapi_uid.get(uid).copied().unwrap_or_default();
+                self.uid(uid).sapi_uid.remain >= weight.saturating_add(reserved)
+            }
+        }
+    }
+
+    /// 权重或次数超出了限速的上限时，即使限速被重置也无法满足，返回错误
+    fn check_limit(
+        &mut self,
+        weight: u32,
+        order: bool,
+        bucket: LimitBucket,
+        uid: &str,
+    ) -> BiAnResult<()> {
+        let check = |kind: BucketKind, n: u32, x: &RestApiRateLimitInfo| {
+            if n > x.rate_limit.limit {
+                return Err(BiAnApiError::ArgumentError(format!(
+                    "{n} exceeds rate limit {kind:?} {}: {}",
+                    x.interval, x.rate_limit.limit
+                )));
+            }
+            Ok(())
         };
+        match bucket {
+            LimitBucket::Api => {
+                for x in self.weights.iter() {
+                    check(BucketKind::Weight, weight, x)?;
+                }
+                for x in self.raw_requests.iter() {
+                    check(BucketKind::RawRequests, 1, x)?;
+                }
+                if order {
+                    for x in self.uid(uid).orders.iter() {
+                        check(BucketKind::Orders, 1, x)?;
+                    }
+                }
+                Ok(())
+            }
+            LimitBucket::SapiIp => check(BucketKind::SapiIpWeight, weight, &self.sapi_ip),
+            LimitBucket::SapiUid => {
+                check(BucketKind::SapiUidWeight, weight, &self.uid(uid).sapi_uid)
+            }
+        }
+    }
+
+    /// 扣除权重和次数，调用前需要先通过`can_take`检查
+    ///
+    /// 共享的限速可能在检查之后被其它进程用完，此时撤销已扣除的部分并返回false
+    fn take(
+        &mut self,
+        weight: u32,
+        order: bool,
+        bucket: LimitBucket,
+        uid: &str,
+        scope: Option<&str>,
+        reserved: &Reserved,
+    ) -> bool {
+        let now_ms = self.clock.now_millis();
+        match bucket {
+            LimitBucket::Api => {
+                if !take_all(&mut self.weights, weight, reserved.weight, now_ms) {
+                    return false;
+                }
+                if !take_all(&mut self.raw_requests, 1, reserved.raw_requests, now_ms) {
+                    self.weights
+                        .iter_mut()
+                        .for_each(|x| x.refund(weight, now_ms));
+                    return false;
+                }
+                self.scope_add(weight, order, uid, scope, false);
+                if order {
+                    // 下单次数按账户计数，不在进程间共享，扣除不会失败
+                    take_all(&mut self.uid(uid).orders, 1, 0, now_ms);
+                }
+                true
+            }
+            LimitBucket::SapiIp => self.sapi_ip.take(weight, reserved.sapi_ip, now_ms),
+            LimitBucket::SapiUid => {
+                let reserved = reserved.sapi_uid.get(uid).copied().unwrap_or_default();
+                self.uid(uid).sapi_uid.take(weight, reserved, now_ms)
+            }
+        }
+    }
+
+    /// 退还已经扣除但没有使用的权重和次数
+    fn refund(
+        &mut self,
+        weight: u32,
+        order: bool,
+        bucket: LimitBucket,
+        uid: &str,
+        scope: Option<&str>,
+    ) {
+        let now_ms = self.clock.now_millis();
+        match bucket {
+            LimitBucket::Api => {
+                self.scope_add(weight, order, uid, scope, true);
+                self.weights
+                    .iter_mut()
+                    .for_each(|x| x.refund(weight, now_ms));
+                self.raw_requests
+                    .iter_mut()
+                    .for_each(|x| x.refund(1, now_ms));
+                if order {
+                    let orders = &mut self.uid(uid).orders;
+                    orders.iter_mut().for_each(|x| x.refund(1, now_ms));
+                }
+            }
+            LimitBucket::SapiIp => self.sapi_ip.refund(weight, now_ms),
+            LimitBucket::SapiUid => self.uid(uid).sapi_uid.refund(weight, now_ms),
+        }
+    }
 
-        let order_sec10_rl = RateLimit {
-            rate_limit_type: RateLimitType::Orders,
-            interval: RateLimitInterVal::Second,
-            interval_num: 10,
-            limit: 100,
-            count: 0,
+    /// /api/*接口的权重和下单次数(按时间段从短到长排列)
+    fn api_buckets(&mut self, order: bool, uid: &str) -> [(BucketKind, Vec<(Interval, u32)>); 2] {
+        let buckets = |x: &[RestApiRateLimitInfo]| -> Vec<(Interval, u32)> {
+            x.iter().map(|x| (x.interval, x.rate_limit.limit)).collect()
+        };
+        let weights = buckets(&self.weights);
+        let orders = if order {
+            buckets(&self.uid(uid).orders)
+        } else {
+            vec![]
         };
+        [(BucketKind::Weight, weights), (BucketKind::Orders, orders)]
+    }
+
+    /// 分区使用的/api/*权重和下单次数的限速规则(按时间段从短到长排列)
+    fn scope_limits(&mut self) -> [(BucketKind, Vec<(Interval, u32)>); 2] {
+        let mut orders: Vec<_> = self
+            .order_limits
+            .iter()
+            .filter_map(|x| Some((Interval::of(x)?, x.limit)))
+            .collect();
+        orders.sort_by_key(|x| x.0.millis());
+        let [weights, _] = self.api_buckets(false, DEFAULT_UID);
+        [weights, (BucketKind::Orders, orders)]
+    }
 
-        let order_day1_rl = RateLimit {
-            rate_limit_type: RateLimitType::Orders,
-            interval: RateLimitInterVal::Day,
-            interval_num: 1,
-            limit: 160000,
-            count: 0,
+    /// 分区内的请求不能超出分区的份额，未分区的请求不能使用分区预留但尚未使用的部分
+    fn scope_allows(&mut self, weight: u32, order: bool, uid: &str, scope: Option<&str>) -> bool {
+        if self.scopes.is_empty() {
+            return true;
+        }
+        let now_ms = self.clock.now_millis();
+        let api_buckets = self.api_buckets(order, uid);
+        if let Some(scope) = scope {
+            let Some(scope) = self.scopes.get_mut(scope) else {
+                return true;
+            };
+            return api_buckets.iter().all(|(kind, buckets)| {
+                let n = if *kind == BucketKind::Weight {
+                    weight
+                } else {
+                    1
+                };
+                let uid = (*kind == BucketKind::Orders).then_some(uid);
+                scope.allows(*kind, uid, buckets, n, now_ms)
+            });
+        }
+
+        for (kind, buckets) in api_buckets.iter() {
+            let mut reserved = vec![0u32; buckets.len()];
+            let scope_uid = (*kind == BucketKind::Orders).then_some(uid);
+            for scope in self.scopes.values_mut() {
+                for (r, n) in reserved
+                    .iter_mut()
+                    .zip(scope.unused(*kind, scope_uid, buckets, now_ms))
+                {
+                    *r = r.saturating_add(n);
+                }
+            }
+            let (remains, n): (Vec<u32>, u32) = if *kind == BucketKind::Weight {
+                (self.weights.iter().map(|x| x.remain).collect(), weight)
+            } else {
+                (self.uid(uid).orders.iter().map(|x| x.remain).collect(), 1)
+            };
+            if remains
+                .iter()
+                .zip(reserved)
+                .any(|(&remain, r)| remain < n.saturating_add(r))
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 记录(或退还)分区使用的权重和下单次数
+    fn scope_add(
+        &mut self,
+        weight: u32,
+        order: bool,
+        uid: &str,
+        scope: Option<&str>,
+        refund: bool,
+    ) {
+        let Some(name) = scope else {
+            return;
+        };
+        if !self.scopes.contains_key(name) {
+            return;
+        }
+        let now_ms = self.clock.now_millis();
+        let [(_, weights), (_, orders)] = self.api_buckets(order, uid);
+        let scope = self.scopes.get_mut(name).unwrap();
+        scope.add(BucketKind::Weight, None, &weights, weight, refund, now_ms);
+        scope.add(BucketKind::Orders, Some(uid), &orders, 1, refund, now_ms);
+        let sign = |x: &mut u64, n: u64| {
+            *x = if refund { x.saturating_sub(n) } else { *x + n };
         };
+        sign(&mut scope.requests, 1);
+        sign(&mut scope.weight, weight as u64);
+        sign(&mut scope.orders, order as u64);
+    }
+
+    fn has_waiters(&self) -> bool {
+        self.waiters.iter().any(|x| !x.is_empty())
+    }
 
-        let raw_requests_rl = RateLimit {
-            rate_limit_type: RateLimitType::RawRequests,
-            interval: RateLimitInterVal::Minute,
-            interval_num: 5,
-            limit: 61000,
-            count: 0,
+    /// 按优先级从高到低、同一优先级先进先出的顺序唤醒等待者
+    ///
+    /// 无法满足的等待者会为自己预留权重，排在其后的请求只能使用剩余的部分，
+    /// 因此大权重的请求不会被小权重的请求饿死，而只是缺少下单次数的请求也不会阻塞其它请求
+    fn dispatch(&mut self) {
+        self.refresh(self.clock.now_millis());
+        let mut reserved = Reserved::default();
+        for p in 0..self.waiters.len() {
+            let mut i = 0;
+            while i < self.waiters[p].len() {
+                let w = &self.waiters[p][i];
+                // 等待者已经取消(例如请求被drop)
+                if w.tx.is_closed() {
+                    self.waiters[p].remove(i);
+                    continue;
+                }
+                let (weight, order, bucket) = (w.weight, w.order, w.bucket);
+                let (uid, scope) = (w.uid.clone(), w.scope.clone());
+                let scope = scope.as_deref();
+                // 限速规则被更新后可能再也无法满足，不为其预留权重
+                if let Err(e) = self.check_limit(weight, order, bucket, &uid) {
+                    let w = self.waiters[p].remove(i).unwrap();
+                    let _ = w.tx.send(Err(e));
+                    continue;
+                }
+                // 超出分区份额的请求只能等待分区的限速被重置，不为其预留权重，以免阻塞其它请求
+                if bucket == LimitBucket::Api
+                    && scope.is_some()
+                    && !self.scope_allows(weight, order, &uid, scope)
+                {
+                    i += 1;
+                    continue;
+                }
+                if !self.can_take(weight, order, bucket, &uid, scope, &reserved)
+                    || !self.take(weight, order, bucket, &uid, scope, &reserved)
+                {
+                    reserved.add(weight, bucket, &uid);
+                    i += 1;
+                    continue;
+                }
+                let w = self.waiters[p].remove(i).unwrap();
+                if w.tx.send(Ok(())).is_err() {
+                    self.refund(weight, order, bucket, &uid, scope);
+                }
+            }
+        }
+        self.check_thresholds();
+    }
+
+    /// 遍历所有的限速，以及其类型和所属账户
+    fn each_bucket(
+        &mut self,
+        mut f: impl FnMut(BucketKind, Option<&str>, &mut RestApiRateLimitInfo),
+    ) {
+        for x in self.weights.iter_mut() {
+            f(BucketKind::Weight, None, x);
+        }
+        for x in self.raw_requests.iter_mut() {
+            f(BucketKind::RawRequests, None, x);
+        }
+        f(BucketKind::SapiIpWeight, None, &mut self.sapi_ip);
+        for (uid, limits) in self.uids.iter_mut() {
+            for x in limits.orders.iter_mut() {
+                f(BucketKind::Orders, Some(uid), x);
+            }
+            f(BucketKind::SapiUidWeight, Some(uid), &mut limits.sapi_uid);
+        }
+    }
+
+    /// 已使用的比例越过了新的阈值时发送事件
+    fn check_thresholds(&mut self) {
+        if self.thresholds.is_empty() {
+            return;
+        }
+        let (thresholds, events) = (self.thresholds.clone(), self.events.clone());
+        self.each_bucket(|kind, uid, x| {
+            let used = used_ratio(x.rate_limit.limit, x.remain);
+            let crossed = thresholds
+                .iter()
+                .filter(|t| t.matches(kind, x.interval) && t.ratio <= used && t.ratio > x.crossed)
+                .map(|t| t.ratio)
+                .reduce(f64::max);
+            if let Some(threshold) = crossed {
+                x.crossed = threshold;
+                let bucket = x.snapshot(kind, uid);
+                warn!(
+                    "rate limit {:?} {} used {:.0}%",
+                    kind,
+                    x.interval,
+                    used * 100.0
+                );
+                let _ = events.send(RateLimitEvent::ThresholdCrossed { bucket, threshold });
+            }
+        });
+    }
+
+    /// 被封禁时，不等待封禁结束的等待者立即返回`BiAnApiError::Banned`
+    fn reject_on_ban(&mut self, until: SystemTime) {
+        for queue in self.waiters.iter_mut() {
+            let (rejected, kept): (VecDeque<_>, _) = std::mem::take(queue)
+                .into_iter()
+                .partition(|x| !x.wait_on_ban);
+            *queue = kept;
+            for w in rejected {
+                let _ = w.tx.send(Err(BiAnApiError::Banned { until }));
+            }
+        }
+        // 预留的权重被释放，后面的请求可能可以被满足了
+        self.dispatch();
+    }
+
+    fn remove_waiter(&mut self, id: u64) -> bool {
+        for queue in self.waiters.iter_mut() {
+            if let Some(i) = queue.iter().position(|x| x.id == id) {
+                queue.remove(i);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// 获取账户的限速，不存在时创建
+    fn uid(&mut self, uid: &str) -> &mut UidLimits {
+        let (order_limits, now_ms) = (&self.order_limits, self.clock.now_millis());
+        self.uids.entry(uid.to_string()).or_insert_with(|| {
+            let order_limits: Vec<_> = order_limits.iter().collect();
+            UidLimits::new(&order_limits, now_ms)
+        })
+    }
+
+    /// 按新的限速规则重建各个限速
+    fn update(&mut self, rate_limits: &[RateLimit], now_ms: i64) {
+        let of_type = |t: RateLimitType| -> Vec<&RateLimit> {
+            rate_limits
+                .iter()
+                .filter(|x| {
+                    std::mem::discriminant(&x.rate_limit_type) == std::mem::discriminant(&t)
+                })
+                .collect()
         };
+        RestApiRateLimitInfo::rebuild(
+            &mut self.weights,
+            &of_type(RateLimitType::RequestWeight),
+            now_ms,
+        );
+        RestApiRateLimitInfo::rebuild(
+            &mut self.raw_requests,
+            &of_type(RateLimitType::RawRequests),
+            now_ms,
+        );
+        let order_limits = of_type(RateLimitType::Orders);
+        if !order_limits.is_empty() {
+            for x in self.uids.values_mut() {
+                RestApiRateLimitInfo::rebuild(&mut x.orders, &order_limits, now_ms);
+            }
+            self.order_limits = order_limits.into_iter().cloned().collect();
+        }
+        #[cfg(feature = "shared-rate-limit")]
+        self.attach_shared();
+    }
 
+    /// 将按IP计数的限速关联到共享文件中的槽
+    #[cfg(feature = "shared-rate-limit")]
+    fn attach_shared(&mut self) {
+        let Some(budget) = &self.shared else {
+            return;
+        };
+        let attach = |x: &mut RestApiRateLimitInfo, kind: SharedKind| {
+            if x.shared.is_none() {
+                x.shared = budget.slot(kind, x.interval.millis());
+                if x.shared.is_none() {
+                    warn!("no free slot in shared rate limit file for {:?}", kind);
+                }
+            }
+        };
+        self.weights
+            .iter_mut()
+            .for_each(|x| attach(x, SharedKind::Weight));
+        self.raw_requests
+            .iter_mut()
+            .for_each(|x| attach(x, SharedKind::RawRequests));
+        attach(&mut self.sapi_ip, SharedKind::SapiIp);
+    }
+
+    /// 重置所有进入了新时间段的限速
+    fn refresh(&mut self, now_ms: i64) {
+        self.weights.iter_mut().for_each(|x| x.refresh(now_ms));
+        self.raw_requests.iter_mut().for_each(|x| x.refresh(now_ms));
+        self.sapi_ip.refresh(now_ms);
+        self.uids.values_mut().for_each(|x| x.refresh(now_ms));
+    }
+
+    /// 距离最近一个限速被重置还有多久
+    fn next_reset(&self, now_ms: i64) -> Duration {
+        let ms = self
+            .weights
+            .iter()
+            .chain(&self.raw_requests)
+            .chain([&self.sapi_ip])
+            .chain(
+                self.uids
+                    .values()
+                    .flat_map(|x| x.orders.iter().chain([&x.sapi_uid])),
+            )
+            .map(|x| x.until_reset(now_ms))
+            .min()
+            .unwrap_or_default();
+        Duration::from_millis(ms.max(0) as u64)
+    }
+}
+
+impl RestApiRateLimitsInner {
+    fn new(events: broadcast::Sender<RateLimitEvent>, clock: Arc<dyn Clock>) -> Self {
+        let now_ms = clock.now_millis();
+        let info = |rl: RateLimit| RestApiRateLimitInfo::new(rl, now_ms).unwrap();
         Self {
-            weight: RestApiRateLimitInfo::new(weight_rl),
-            order_sec10: RestApiRateLimitInfo::new(order_sec10_rl),
-            order_day1: RestApiRateLimitInfo::new(order_day1_rl),
-            raw_requests: RestApiRateLimitInfo::new(raw_requests_rl),
+            weights: vec![info(weight_1m(6000))],
+            raw_requests: vec![info(rate_limit(
+                RateLimitType::RawRequests,
+                RateLimitInterVal::Minute,
+                5,
+                61000,
+            ))],
+            sapi_ip: info(weight_1m(SAPI_IP_WEIGHT_LIMIT)),
+            uids: HashMap::new(),
+            order_limits: vec![
+                rate_limit(RateLimitType::Orders, RateLimitInterVal::Second, 10, 100),
+                rate_limit(RateLimitType::Orders, RateLimitInterVal::Day, 1, 160000),
+            ],
+            scopes: HashMap::new(),
+            thresholds: Arc::default(),
+            events,
+            waiters: Default::default(),
+            next_waiter_id: 0,
+            clock,
+            #[cfg(feature = "shared-rate-limit")]
+            shared: None,
         }
     }
 }
@@ -122,120 +1270,329 @@ impl Default for RestApiRateLimitsInner {
 #[derive(Clone)]
 pub(crate) struct RestApiRateLimits {
     inner: Arc<RwLock<RestApiRateLimitsInner>>,
+    /// 被封禁到什么时候，所有克隆共享
+    ban_until: Arc<Mutex<Option<SystemTime>>>,
+    events: broadcast::Sender<RateLimitEvent>,
+    clock: Arc<dyn Clock>,
 }
 
 impl RestApiRateLimits {
+    /// 限速的时间段、封禁时间以及等待都按clock计算，
     /// 获取到exchange_info信息之后，应立即调用 `update()` 方法进行更新
-    pub async fn new() -> Self {
+    pub async fn new(clock: Arc<dyn Clock>) -> Self {
+        let (events, _) = broadcast::channel(16);
+        let inner = RestApiRateLimitsInner::new(events.clone(), clock.clone());
         let s = Self {
-            inner: Arc::new(RwLock::new(RestApiRateLimitsInner::default())),
+            inner: Arc::new(RwLock::new(inner)),
+            ban_until: Arc::default(),
+            events,
+            clock,
         };
 
-        let ss = s.clone();
-        tokio::spawn(async move {
-            ss.run_tick().await;
-        });
+        tokio::spawn(Self::run_tick(Arc::downgrade(&s.inner), s.clock.clone()));
 
         s
     }
 
-    /// 根据给定的exchange_info更新限速规则的最大值，而不是继续使用默认值
+    /// 根据给定的exchange_info重建限速规则，而不是继续使用默认值，
+    /// 已有的同一时间段的限速保留已使用的值，exchange_info中没有的限速类型继续使用原有的规则
     pub async fn update(&self, exchange_info: &ExchangeInfo) {
-        let limits = &exchange_info.rate_limits;
-        let weight = limits
-            .iter()
-            .find(|x| matches!(x.rate_limit_type, RateLimitType::RequestWeight))
-            .map(|x| x.limit);
+        let mut inner = self.inner.write().await;
+        inner.update(&exchange_info.rate_limits, self.clock.now_millis());
+        inner.dispatch();
+    }
 
-        let order_sec10 = limits
-            .iter()
-            .find(|x| {
-                matches!(x.rate_limit_type, RateLimitType::Orders)
-                    && matches!(x.interval, RateLimitInterVal::Second)
-            })
-            .map(|x| x.limit);
+    /// 与同一台机器上的其它进程共享按IP计数的限速(权重、请求次数、SAPI的IP权重)，
+    /// 所有进程使用同一个文件路径，下单次数和SAPI的UID权重不共享
+    #[cfg(feature = "shared-rate-limit")]
+    pub async fn share(&self, path: impl AsRef<std::path::Path>) -> BiAnResult<()> {
+        let budget = SharedBudget::open(path)?;
+        let mut inner = self.inner.write().await;
+        inner.shared = Some(budget);
+        inner.attach_shared();
+        inner.refresh(self.clock.now_millis());
+        Ok(())
+    }
 
-        let order_day1 = limits
-            .iter()
-            .find(|x| {
-                matches!(x.rate_limit_type, RateLimitType::Orders)
-                    && matches!(x.interval, RateLimitInterVal::Day)
-            })
-            .map(|x| x.limit);
+    /// 设置使用率阈值，替换已有的阈值
+    pub async fn set_thresholds(&self, thresholds: Vec<Threshold>) {
+        let mut inner = self.inner.write().await;
+        inner.thresholds = Arc::new(thresholds);
+        inner.check_thresholds();
+    }
 
-        let raw_requests = limits
-            .iter()
-            .find(|x| matches!(x.rate_limit_type, RateLimitType::RawRequests))
-            .map(|x| x.limit);
+    /// 添加限速分区，分区已存在时只更新其份额
+    ///
+    /// 份额超出了限速的上限，或者加上其它分区后份额之和超出了限速的上限时返回错误
+    pub async fn add_scope(&self, name: &str, quota: Quota) -> BiAnResult<()> {
+        let mut inner = self.inner.write().await;
+        for (kind, buckets) in inner.scope_limits() {
+            let shortest = buckets.first().map(|x| x.1).unwrap_or_default();
+            let ratio = quota.ratio(kind, shortest);
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(BiAnApiError::ArgumentError(format!(
+                    "scope `{name}' quota {quota:?} exceeds rate limit {kind:?}: {shortest}"
+                )));
+            }
+            let total = inner
+                .scopes
+                .iter()
+                .filter(|(x, _)| x.as_str() != name)
+                .map(|(_, x)| x.quota.ratio(kind, shortest))
+                .sum::<f64>()
+                + ratio;
+            // 容忍浮点数的误差
+            if total > 1.0 + 1e-9 {
+                return Err(BiAnApiError::ArgumentError(format!(
+                    "quotas of all scopes exceed rate limit {kind:?}: {:.1}%",
+                    total * 100.0
+                )));
+            }
+        }
+        inner
+            .scopes
+            .entry(name.to_string())
+            .and_modify(|x| x.quota = quota)
+            .or_insert_with(|| Scope::new(quota));
+        // 份额变小时，等待中的请求可能可以被满足了
+        inner.dispatch();
+        Ok(())
+    }
 
+    /// 所有限速分区的使用统计
+    pub async fn scope_stats(&self) -> Vec<ScopeStats> {
         let mut inner = self.inner.write().await;
-        if let Some(limit) = weight {
-            inner.weight.rate_limit.limit = limit;
+        let now_ms = self.clock.now_millis();
+        let limits = inner.scope_limits();
+
+        let mut stats: Vec<_> = inner
+            .scopes
+            .iter_mut()
+            .map(|(name, scope)| {
+                let mut buckets = vec![];
+                for (kind, x) in limits.iter() {
+                    let kind = *kind;
+                    // 下单次数按账户统计，没有下单时只列出RestConn自身的账户
+                    let mut uids = vec![];
+                    if kind == BucketKind::Orders {
+                        uids = scope
+                            .usage
+                            .iter()
+                            .filter(|x| x.kind == kind)
+                            .map(|x| x.uid.clone())
+                            .collect();
+                        uids.sort();
+                        uids.dedup();
+                        if uids.is_empty() {
+                            uids.push(Some(DEFAULT_UID.to_string()));
+                        }
+                    } else {
+                        uids.push(None);
+                    }
+                    let shortest = x.first().map(|x| x.1).unwrap_or_default();
+                    for uid in uids.iter() {
+                        for &(interval, limit) in x.iter() {
+                            let limit = scope.quota.of(kind, limit, shortest);
+                            let used = *scope.used(kind, uid.as_deref(), interval, now_ms);
+                            let reset_ms = interval.window_start(now_ms) + interval.millis();
+                            buckets.push(BucketSnapshot {
+                                kind,
+                                interval,
+                                uid: uid.clone(),
+                                limit,
+                                remain: limit.saturating_sub(used),
+                                reset_at: UNIX_EPOCH + Duration::from_millis(reset_ms as u64),
+                            });
+                        }
+                    }
+                }
+                ScopeStats {
+                    name: name.clone(),
+                    quota: scope.quota,
+                    requests: scope.requests,
+                    weight: scope.weight,
+                    orders: scope.orders,
+                    buckets,
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+        stats
+    }
+
+    /// 所有限速的当前状态
+    pub async fn snapshot(&self) -> Vec<BucketSnapshot> {
+        let mut inner = self.inner.write().await;
+        inner.refresh(self.clock.now_millis());
+        let mut snapshot = vec![];
+        inner.each_bucket(|kind, uid, x| snapshot.push(x.snapshot(kind, uid)));
+        snapshot
+    }
+
+    /// 订阅限速相关的事件
+    pub fn subscribe(&self) -> broadcast::Receiver<RateLimitEvent> {
+        self.events.subscribe()
+    }
+
+    /// 收到418或429后，记录封禁状态，封禁时长取自Retry-After，
+    /// 已经处于封禁状态时，只会延长而不会缩短封禁时间，
+    /// 等待队列中不等待封禁结束的请求立即返回`BiAnApiError::Banned`
+    pub async fn ban(&self, status: u16, retry_after: Option<Duration>) {
+        let until = self.clock.system_time() + retry_after.unwrap_or(DEFAULT_BAN_DURATION);
+        {
+            let mut ban_until = self.ban_until.lock().unwrap();
+            if ban_until.is_some_and(|x| x >= until) {
+                return;
+            }
+            *ban_until = Some(until);
         }
-        if let Some(limit) = order_sec10 {
-            inner.order_sec10.rate_limit.limit = limit;
+        warn!("received {}, pause all requests until {:?}", status, until);
+        let _ = self.events.send(RateLimitEvent::Banned { status, until });
+        self.inner.write().await.reject_on_ban(until);
+    }
+
+    /// 当前是否处于封禁状态，是则返回封禁的结束时间
+    pub fn banned_until(&self) -> Option<SystemTime> {
+        let mut ban_until = self.ban_until.lock().unwrap();
+        match *ban_until {
+            Some(until) if until > self.clock.system_time() => Some(until),
+            Some(_) => {
+                *ban_until = None;
+                None
+            }
+            None => None,
         }
-        if let Some(limit) = order_day1 {
-            inner.order_day1.rate_limit.limit = limit;
+    }
+
+    /// 处于封禁状态时，wait为true则等待封禁结束，否则返回`BiAnApiError::Banned`
+    pub async fn wait_ban(&self, wait: bool) -> BiAnResult<()> {
+        if !wait && let Some(until) = self.banned_until() {
+            return Err(BiAnApiError::Banned { until });
         }
-        if let Some(limit) = raw_requests {
-            inner.raw_requests.rate_limit.limit = limit;
+        self.sleep_until_unbanned().await;
+        Ok(())
+    }
+
+    async fn sleep_until_unbanned(&self) {
+        while let Some(until) = self.banned_until() {
+            let remain = until
+                .duration_since(self.clock.system_time())
+                .unwrap_or_default();
+            self.clock.sleep(remain).await;
         }
     }
 
-    /// 尝试获取 n 个权重值(如果剩余权重值不够，将一直等待，直到权重值足够)
-    pub async fn acquire_permits(&self, limit_param: RateLimitParam) {
-        let (n, order_flag) = match limit_param {
-            RateLimitParam::Weight(n) => (n, false),
-            RateLimitParam::Order(n) => (n, true),
-            RateLimitParam::Both(n) => (n, true),
-        };
+    /// 从uids中选择剩余下单次数最多的账户(按时间段从短到长比较)，以免占用下单次数紧张的账户
+    pub(crate) async fn least_used_uid<'a>(
+        &self,
+        uids: impl Iterator<Item = &'a str>,
+    ) -> Option<&'a str> {
+        let inner = self.inner.read().await;
+        let now_ms = self.clock.now_millis();
+        uids.max_by_key(|uid| {
+            // 尚未发送过请求的账户，所有限速都是满的
+            let Some(x) = inner.uids.get(*uid) else {
+                return vec![u32::MAX];
+            };
+            x.orders.iter().map(|x| x.remain_at(now_ms)).collect()
+        })
+    }
 
-        loop {
+    /// 从bucket对应的限速中获取权重值，下单请求还需获取uid账户的下单次数，
+    /// 按UID计数的/sapi/*请求从uid账户的SAPI UID权重中获取
+    ///
+    /// 剩余权重不够时，请求将进入等待队列，直到限速被重置后按优先级和先后顺序被唤醒，
+    /// 等待超过timeout时返回`BiAnApiError::RateLimitTimeout`，timeout为None时一直等待
+    ///
+    /// 处于封禁状态时，wait_on_ban为true则先等待封禁结束，否则返回`BiAnApiError::Banned`，
+    /// 在等待队列中时被封禁也同样返回
+    #[allow(clippy::too_many_arguments)]
+    pub async fn acquire_permits(
+        &self,
+        limit_param: RateLimitParam,
+        bucket: LimitBucket,
+        uid: &str,
+        scope: Option<&str>,
+        priority: RequestPriority,
+        timeout: Option<Duration>,
+        wait_on_ban: bool,
+    ) -> BiAnResult<()> {
+        let (weight, order) = limit_param.need();
+        self.wait_ban(wait_on_ban).await?;
+
+        let (id, mut rx) = {
             let mut inner = self.inner.write().await;
+            inner.refresh(self.clock.now_millis());
+            inner.check_limit(weight, order, bucket, uid)?;
+            // 没有其它请求在等待时，直接获取
+            let reserved = Reserved::default();
+            if !inner.has_waiters()
+                && inner.can_take(weight, order, bucket, uid, scope, &reserved)
+                && inner.take(weight, order, bucket, uid, scope, &reserved)
+            {
+                inner.check_thresholds();
+                return Ok(());
+            }
 
-            if order_flag {
-                if inner.weight.remain >= n
-                    && inner.raw_requests.remain >= 1
-                    && inner.order_sec10.remain >= 1
-                    && inner.order_day1.remain >= 1
-                {
-                    inner.weight.remain -= n;
-                    inner.raw_requests.remain -= 1;
-                    inner.order_sec10.remain -= 1;
-                    inner.order_day1.remain -= 1;
-                    break;
+            let (tx, rx) = oneshot::channel();
+            let id = inner.next_waiter_id;
+            inner.next_waiter_id += 1;
+            inner.waiters[priority as usize].push_back(Waiter {
+                id,
+                weight,
+                order,
+                bucket,
+                uid: uid.to_string(),
+                scope: scope.map(String::from),
+                wait_on_ban,
+                tx,
+            });
+            inner.dispatch();
+            (id, rx)
+        };
+
+        let granted = match timeout {
+            Some(t) => tokio::select! {
+                x = &mut rx => Some(x),
+                _ = self.clock.sleep(t) => None,
+            },
+            None => Some((&mut rx).await),
+        };
+        match granted {
+            Some(Ok(r)) => r,
+            Some(Err(_)) => Err(BiAnApiError::Unknown("rate limit waiter dropped".into())),
+            None => {
+                let mut inner = self.inner.write().await;
+                if inner.remove_waiter(id) {
+                    // 预留的权重被释放，后面的请求可能可以被满足了
+                    inner.dispatch();
+                    warn!("wait rate limit permits timeout after {:?}", timeout);
+                    return Err(BiAnApiError::RateLimitTimeout(timeout.unwrap_or_default()));
                 }
-            } else if inner.weight.remain >= n && inner.raw_requests.remain >= 1 {
-                inner.weight.remain -= n;
-                inner.raw_requests.remain -= 1;
-                break;
+                // 超时的同时已经获取到了权重
+                drop(inner);
+                rx.try_recv()
+                    .map_err(|_| BiAnApiError::Unknown("rate limit waiter dropped".into()))?
             }
-
-            drop(inner);
-            tokio::task::yield_now().await;
         }
     }
 
     /// 更新限速的值，传递的是当前限速时间段内已经使用的值
     ///
+    /// uid: 发送请求的账户，下单次数和SAPI UID权重只更新到该账户
     /// date: 该Rest响应是在什么时间点发出的(格式"Fri, 25 Aug 2023 10:14:35 GMT")
-    pub async fn set_permits(
-        &self,
-        response_date: String,
-        weight_used: Option<u32>,
-        order_sec10: Option<u32>,
-        order_day1: Option<u32>,
-    ) {
-        let response_date = match response_date.to_dt_east0("%a, %d %b %Y %T %Z") {
-            Ok(dt) => dt,
+    pub async fn set_permits(&self, uid: &str, response_date: String, used: UsedPermits) {
+        let now_ms = self.clock.now_millis();
+        let response_ms = match response_date.to_dt_east0("%a, %d %b %Y %T %Z") {
+            Ok(dt) => dt.timestamp_millis(),
             Err(_) => {
                 error!("parse str({}) to datetime", response_date);
-                now0()
+                now_ms
             }
         };
 
         let mut inner = self.inner.write().await;
+        inner.refresh(now_ms);
 
         /*
          * 实际消耗的值(n)和计算消耗的值(max_limit - remain)，两者取max，
@@ -252,102 +1609,830 @@ impl RestApiRateLimits {
          * 因此，通过判断 response_date 的响应时间来判断该响应发生在重置前还是重置后，
          * 只有响应发生在重置后，才更新当前已经消耗的值
          */
-        if inner.weight.reset_datetime < response_date
-            && let Some(n) = weight_used
-        {
-            let used = (inner.weight.rate_limit.limit - inner.weight.remain).max(n);
-            inner.weight.remain = inner.weight.rate_limit.limit - used;
+        let find = |used: &[(Interval, u32)], x: &RestApiRateLimitInfo| {
+            used.iter().find(|(i, _)| *i == x.interval).map(|(_, n)| *n)
+        };
+        for x in inner.weights.iter_mut() {
+            x.set_used(find(&used.weight, x), response_ms, now_ms);
+        }
+        inner.sapi_ip.set_used(used.sapi_ip_1m, response_ms, now_ms);
+
+        let limits = inner.uid(uid);
+        for x in limits.orders.iter_mut() {
+            x.set_used(find(&used.order_count, x), response_ms, now_ms);
         }
+        limits
+            .sapi_uid
+            .set_used(used.sapi_uid_1m, response_ms, now_ms);
+        inner.dispatch();
+    }
+}
 
-        if inner.order_sec10.reset_datetime < response_date
-            && let Some(n) = order_sec10
+impl RestApiRateLimits {
+    /// tick，到了任一限速时间段的结束点就重置该限速，并唤醒等待者，
+    /// 只持有inner的弱引用，所有克隆都被drop后退出
+    async fn run_tick(inner: Weak<RwLock<RestApiRateLimitsInner>>, clock: Arc<dyn Clock>) {
+        loop {
+            let Some(limits) = inner.upgrade() else {
+                return;
+            };
+            let wait = limits.read().await.next_reset(clock.now_millis());
+            drop(limits);
+            clock.sleep(wait.min(MAX_TICK_INTERVAL)).await;
+            let Some(limits) = inner.upgrade() else {
+                return;
+            };
+            // dispatch时会重置所有进入了新时间段的限速
+            limits.write().await.dispatch();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::{
+        BucketKind, Interval, LimitBucket, MAX_TICK_INTERVAL, Quota, RateLimitEvent,
+        RateLimitParam, RequestPriority, RestApiRateLimits, Threshold, UsedPermits, rate_limit,
+    };
+    use crate::{
+        client::{
+            clock::{Clock, ManualClock},
+            exchange_info::parse_exchange_info,
+            params::PRateLimit,
+        },
+        errors::BiAnApiError,
+    };
+    use ba_types::{RateLimitInterVal, RateLimitType};
+    use chrono_ext::ParseDateTimeExt;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// 2023-08-24 23:00:00 UTC
+    const START_MS: i64 = 1_692_918_000_000;
+
+    /// 等待请求进入等待队列后推进时钟，使设置了超时的请求超时
+    async fn advance_waiting<F>(
+        limits: &RestApiRateLimits,
+        clock: &ManualClock,
+        acquire: F,
+    ) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let waiting = tokio::spawn(acquire);
+        while !waiting.is_finished() && !limits.inner.read().await.has_waiters() {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_millis(50));
+        waiting.await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ban() {
+        let clock = ManualClock::new(START_MS);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let mut events = limits.subscribe();
+        assert!(limits.wait_ban(false).await.is_ok());
+
+        limits.ban(429, Some(Duration::from_secs(60))).await;
+        let until = limits.banned_until().unwrap();
+        assert_eq!(
+            events.recv().await.unwrap(),
+            RateLimitEvent::Banned { status: 429, until }
+        );
+        // 更短的封禁时间不会覆盖已有的封禁
+        limits.ban(429, Some(Duration::from_secs(1))).await;
+        assert_eq!(limits.banned_until(), Some(until));
+        assert!(limits.wait_ban(false).await.is_err());
+
+        // 封禁结束后才返回
+        let waiting = tokio::spawn({
+            let limits = limits.clone();
+            async move { limits.wait_ban(true).await }
+        });
+        clock.advance(Duration::from_secs(30));
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        clock.advance(Duration::from_secs(30));
+        waiting.await.unwrap().unwrap();
+        assert!(limits.banned_until().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_ban_waiters() {
+        let clock = ManualClock::new(START_MS);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let acquire = |n: u32, wait_on_ban: bool| {
+            let limits = limits.clone();
+            async move {
+                limits
+                    .acquire_permits(
+                        RateLimitParam::Weight(n),
+                        LimitBucket::Api,
+                        "",
+                        None,
+                        RequestPriority::Normal,
+                        None,
+                        wait_on_ban,
+                    )
+                    .await
+            }
+        };
+        acquire(6000, false).await.unwrap();
+        let rejected = tokio::spawn(acquire(10, false));
+        let waiting = tokio::spawn(acquire(10, true));
+        while limits
+            .inner
+            .read()
+            .await
+            .waiters
+            .iter()
+            .map(|x| x.len())
+            .sum::<usize>()
+            < 2
         {
-            let used = (inner.order_sec10.rate_limit.limit - inner.order_sec10.remain).max(n);
-            inner.order_sec10.remain = inner.order_sec10.rate_limit.limit - used;
+            tokio::task::yield_now().await;
+        }
+
+        // 被封禁时，等待队列中不等待封禁结束的请求立即返回错误，其它请求继续等待
+        limits.ban(418, Some(Duration::from_secs(60))).await;
+        let until = limits.banned_until().unwrap();
+        assert!(matches!(
+            rejected.await.unwrap(),
+            Err(BiAnApiError::Banned { until: x }) if x == until
+        ));
+        assert!(!waiting.is_finished());
+        assert!(matches!(
+            acquire(1, false).await,
+            Err(BiAnApiError::Banned { .. })
+        ));
+
+        // 限速重置后，继续等待的请求获取到权重
+        clock.advance(Duration::from_secs(60));
+        waiting.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tick_exits() {
+        let clock = ManualClock::new(START_MS);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let tick = tokio::spawn(RestApiRateLimits::run_tick(
+            Arc::downgrade(&limits.inner),
+            Arc::new(clock.clone()),
+        ));
+        tokio::task::yield_now().await;
+        assert!(!tick.is_finished());
+
+        // 所有克隆都被drop后，tick任务在下一次醒来时退出
+        drop(limits);
+        clock.advance(MAX_TICK_INTERVAL);
+        tick.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_uid_orders() {
+        let limits = RestApiRateLimits::new(Arc::new(ManualClock::new(START_MS))).await;
+        for uid in ["a", "a", "b"] {
+            limits
+                .acquire_permits(
+                    RateLimitParam::Order(1),
+                    LimitBucket::Api,
+                    uid,
+                    None,
+                    RequestPriority::High,
+                    None,
+                    true,
+                )
+                .await
+                .unwrap();
         }
 
-        if inner.order_day1.reset_datetime < response_date
-            && let Some(n) = order_day1
+        // 每个账户的下单次数独立计数，但共享IP的权重
+        let uid = limits.least_used_uid(["a", "b", "c"].into_iter()).await;
+        assert_eq!(uid, Some("c"));
+        let uid = limits.least_used_uid(["a", "b"].into_iter()).await;
+        assert_eq!(uid, Some("b"));
+        assert_eq!(limits.inner.read().await.weights[0].remain, 6000 - 3);
+    }
+
+    #[tokio::test]
+    async fn test_priority_and_timeout() {
+        let clock = ManualClock::new(START_MS);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let acquire = |n: u32, priority: RequestPriority, timeout: Option<Duration>| {
+            let limits = limits.clone();
+            async move {
+                limits
+                    .acquire_permits(
+                        RateLimitParam::Weight(n),
+                        LimitBucket::Api,
+                        "",
+                        None,
+                        priority,
+                        timeout,
+                        true,
+                    )
+                    .await
+            }
+        };
+
+        acquire(5990, RequestPriority::Normal, None).await.unwrap();
+        // 权重不足时超时返回错误
+        let timeout = Some(Duration::from_millis(50));
+        let res = advance_waiting(
+            &limits,
+            &clock,
+            acquire(20, RequestPriority::Normal, timeout),
+        )
+        .await;
+        assert!(res.is_err());
+
+        // 等待中的请求按优先级被唤醒
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        for (i, (name, priority)) in [
+            ("low", RequestPriority::Low),
+            ("high", RequestPriority::High),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let (acquire, tx) = (acquire(20, priority, None), tx.clone());
+            tokio::spawn(async move {
+                acquire.await.unwrap();
+                tx.send(name).unwrap();
+            });
+            while limits
+                .inner
+                .read()
+                .await
+                .waiters
+                .iter()
+                .map(|x| x.len())
+                .sum::<usize>()
+                <= i
+            {
+                tokio::task::yield_now().await;
+            }
+        }
         {
-            let used = (inner.order_day1.rate_limit.limit - inner.order_day1.remain).max(n);
-            inner.order_day1.remain = inner.order_day1.rate_limit.limit - used;
+            let mut inner = limits.inner.write().await;
+            inner.weights[0].reset_permits(clock.now_millis());
+            inner.dispatch();
         }
+        assert_eq!(rx.recv().await, Some("high"));
+        assert_eq!(rx.recv().await, Some("low"));
     }
-}
 
-impl RestApiRateLimits {
-    /// tick，到了限速时间段的结束点就自动重置限速值
-    pub async fn run_tick(&self) {
-        let now = now0();
-        let now_epoch = now.timestamp_millis();
+    #[tokio::test]
+    async fn test_sapi_buckets() {
+        assert_eq!(
+            LimitBucket::of("/sapi/v1/spot/delist-schedule", PRateLimit::ApiIp),
+            Some(LimitBucket::SapiIp)
+        );
+        assert_eq!(
+            LimitBucket::of("/sapi/v1/asset/dust", PRateLimit::ApiUid),
+            Some(LimitBucket::SapiUid)
+        );
+        assert_eq!(
+            LimitBucket::of("/api/v3/order", PRateLimit::ApiUid),
+            Some(LimitBucket::Api)
+        );
+        assert_eq!(LimitBucket::of("/wapi/v1/x", PRateLimit::ApiIp), None);
 
-        // 10秒间隔，每10秒tick一次
-        let tick_intv = tokio::time::Duration::from_secs(10);
+        let limits = RestApiRateLimits::new(Arc::new(ManualClock::new(START_MS))).await;
+        let acquire = |bucket: LimitBucket, uid: &'static str| {
+            let limits = limits.clone();
+            async move {
+                limits
+                    .acquire_permits(
+                        RateLimitParam::Weight(100),
+                        bucket,
+                        uid,
+                        None,
+                        RequestPriority::Normal,
+                        None,
+                        true,
+                    )
+                    .await
+            }
+        };
+        acquire(LimitBucket::SapiIp, "a").await.unwrap();
+        acquire(LimitBucket::SapiUid, "a").await.unwrap();
+        acquire(LimitBucket::SapiUid, "b").await.unwrap();
 
-        // 下一个整10秒的epoch时间点，将从此开始进行tick
-        let next_sec10_epoch = (now_epoch - now_epoch % 10_000) + 10_000;
-        let start_tick = tokio::time::Instant::now()
-            + tokio::time::Duration::from_millis((next_sec10_epoch - now_epoch) as u64);
-        let mut ticker = tokio::time::interval_at(start_tick, tick_intv);
+        let used = UsedPermits {
+            sapi_uid_1m: Some(1000),
+            ..Default::default()
+        };
+        limits
+            .set_permits("a", "Thu, 24 Aug 2023 23:00:00 GMT".to_string(), used)
+            .await;
 
-        loop {
-            ticker.tick().await;
-
-            let now = now0();
-            let (h, m, s) = (now.hour(), now.minute(), now.second());
-
-            if s % 10 == 0 {
-                // 到了每日的整点
-                if h == 0 && m == 0 && s == 0 {
-                    self.reset_weight_permits().await;
-                    self.reset_order_sec10_permits().await;
-                    self.reset_order_day1_permits().await;
-                    self.reset_raw_request_permits().await;
-                    continue;
-                }
+        // SAPI的权重不消耗/api的IP权重，UID权重每个账户独立计数
+        let mut inner = limits.inner.write().await;
+        assert_eq!(inner.weights[0].remain, 6000);
+        assert_eq!(inner.sapi_ip.remain, 12000 - 100);
+        assert_eq!(inner.uid("a").sapi_uid.remain, 180000 - 1000);
+        assert_eq!(inner.uid("b").sapi_uid.remain, 180000 - 100);
+    }
 
-                // 到了5分钟的整点
-                if m % 5 == 0 && s == 0 {
-                    self.reset_raw_request_permits().await;
-                    continue;
-                }
+    #[tokio::test]
+    async fn test_dynamic_intervals() {
+        let x = Interval::parse("10s").unwrap();
+        assert_eq!(x.to_string(), "10s");
+        assert_eq!(x.window_start(1_234_567), 1_230_000);
+        assert_eq!(
+            Interval::parse("5m").unwrap().window_start(599_999),
+            300_000
+        );
+        assert!(Interval::parse("1x").is_none() && Interval::parse("m").is_none());
 
-                // 到了每分钟的整点
-                if s == 0 {
-                    self.reset_weight_permits().await;
-                    continue;
-                }
+        let clock = ManualClock::new(START_MS);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let rate_limits = [
+            rate_limit(
+                RateLimitType::RequestWeight,
+                RateLimitInterVal::Minute,
+                1,
+                6000,
+            ),
+            rate_limit(
+                RateLimitType::RequestWeight,
+                RateLimitInterVal::Day,
+                1,
+                100000,
+            ),
+            rate_limit(RateLimitType::Orders, RateLimitInterVal::Second, 10, 50),
+        ];
+        limits
+            .inner
+            .write()
+            .await
+            .update(&rate_limits, clock.now_millis());
+        limits
+            .acquire_permits(
+                RateLimitParam::Order(10),
+                LimitBucket::Api,
+                "a",
+                None,
+                RequestPriority::High,
+                None,
+                true,
+            )
+            .await
+            .unwrap();
+
+        // 响应头按时间段匹配到对应的限速
+        let counters = HashMap::from([
+            ("x-mbx-used-weight-1d".to_string(), 500),
+            ("x-mbx-order-count-10s".to_string(), 7),
+            ("x-mbx-order-count-1d".to_string(), 9),
+        ]);
+        let date = "Thu, 24 Aug 2023 23:00:00 GMT".to_string();
+        limits
+            .set_permits("a", date, UsedPermits::from_counters(&counters))
+            .await;
+
+        let mut inner = limits.inner.write().await;
+        assert_eq!(inner.weights.len(), 2);
+        assert_eq!(inner.weights[0].remain, 6000 - 10);
+        assert_eq!(inner.weights[1].remain, 100000 - 500);
+        let orders = &inner.uid("a").orders;
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].interval, Interval::parse("10s").unwrap());
+        assert_eq!(orders[0].remain, 50 - 7);
 
-                // 到了每10秒的整点
-                self.reset_order_sec10_permits().await;
+        // 进入新的时间段后各自重置，每天的限速在UTC零点重置
+        clock.advance(Duration::from_secs(60));
+        inner.refresh(clock.now_millis());
+        assert_eq!(inner.weights[0].remain, 6000);
+        assert_eq!(inner.uid("a").orders[0].remain, 50);
+        assert_eq!(inner.weights[1].remain, 100000 - 500);
+        assert_eq!(
+            inner.next_reset(clock.now_millis()),
+            Duration::from_secs(10)
+        );
+        clock.advance(Duration::from_secs(3600));
+        inner.refresh(clock.now_millis());
+        assert_eq!(inner.weights[1].remain, 100000);
+        assert_eq!(
+            inner.raw_requests[0].interval,
+            Interval::parse("5m").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unknown_interval() {
+        let clock = ManualClock::new(START_MS);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let exchange_info = parse_exchange_info(
+            r#"{
+                "timezone": "UTC",
+                "serverTime": 1692918000000,
+                "rateLimits": [
+                    { "rateLimitType": "REQUEST_WEIGHT", "interval": "MINUTE", "intervalNum": 1, "limit": 3000 },
+                    { "rateLimitType": "REQUEST_WEIGHT", "interval": "HOUR", "intervalNum": 1, "limit": 50000 },
+                    { "rateLimitType": "ORDERS", "interval": "SECOND", "intervalNum": 10, "limit": 50 }
+                ],
+                "exchangeFilters": [],
+                "symbols": []
+            }"#,
+        )
+        .unwrap();
+
+        // 不认识的HOUR时间段被忽略，其它限速规则照常生效
+        assert_eq!(exchange_info.rate_limits.len(), 2);
+        limits.update(&exchange_info).await;
+        let mut inner = limits.inner.write().await;
+        assert_eq!(inner.weights.len(), 1);
+        assert_eq!(inner.weights[0].rate_limit.limit, 3000);
+        assert_eq!(inner.uid("a").orders.len(), 1);
+        assert_eq!(inner.uid("a").orders[0].rate_limit.limit, 50);
+    }
+
+    #[cfg(feature = "shared-rate-limit")]
+    #[tokio::test]
+    async fn test_shared_budget() {
+        let path = std::env::temp_dir().join(format!("ba_api_limit_{}", uuid::Uuid::new_v4()));
+        // 模拟同一台机器上的两个进程
+        let clock = ManualClock::new(START_MS);
+        let a = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let b = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        a.share(&path).await.unwrap();
+        b.share(&path).await.unwrap();
+
+        let acquire = |limits: &RestApiRateLimits, n: u32| {
+            let limits = limits.clone();
+            async move {
+                limits
+                    .acquire_permits(
+                        RateLimitParam::Weight(n),
+                        LimitBucket::Api,
+                        "",
+                        None,
+                        RequestPriority::Normal,
+                        Some(Duration::from_millis(50)),
+                        true,
+                    )
+                    .await
             }
+        };
+        acquire(&a, 4000).await.unwrap();
+        // b看到了a消耗的权重
+        assert!(
+            advance_waiting(&b, &clock, acquire(&b, 2500))
+                .await
+                .is_err()
+        );
+        acquire(&b, 1500).await.unwrap();
+        assert_eq!(a.inner.write().await.weights[0].remain, 6000 - 4000);
+        // a的剩余值已经过时，扣除时在同一个CAS中再次检查，不会超出限速
+        {
+            let mut inner = a.inner.write().await;
+            let reserved = super::Reserved::default();
+            assert!(inner.can_take(1000, false, LimitBucket::Api, "", None, &reserved));
+            assert!(!inner.take(1000, false, LimitBucket::Api, "", None, &reserved));
+            assert_eq!(inner.weights[0].remain, 6000 - 5500);
+        }
+        {
+            let mut inner = a.inner.write().await;
+            inner.refresh(clock.now_millis());
+            assert_eq!(inner.weights[0].remain, 6000 - 5500);
         }
+        let _ = std::fs::remove_file(&path);
     }
 
-    async fn reset_weight_permits(&self) {
-        let mut x = self.inner.write().await;
-        x.weight.reset_permits();
+    #[tokio::test]
+    async fn test_snapshot_and_thresholds() {
+        let clock = ManualClock::new(START_MS);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let mut events = limits.subscribe();
+        limits
+            .set_thresholds(vec![
+                Threshold::new(BucketKind::Weight, 0.5),
+                Threshold::new(BucketKind::Weight, 0.8),
+                Threshold::new(BucketKind::Orders, 0.5).interval(Interval::parse("1d").unwrap()),
+            ])
+            .await;
+
+        let acquire = |limit_param: RateLimitParam| {
+            limits.acquire_permits(
+                limit_param,
+                LimitBucket::Api,
+                "a",
+                None,
+                RequestPriority::Normal,
+                None,
+                true,
+            )
+        };
+        acquire(RateLimitParam::Order(1000)).await.unwrap();
+        // 同时越过0.5和0.8时只报告0.8
+        acquire(RateLimitParam::Weight(4000)).await.unwrap();
+        match events.recv().await.unwrap() {
+            RateLimitEvent::ThresholdCrossed { bucket, threshold } => {
+                assert_eq!(threshold, 0.8);
+                assert_eq!(bucket.kind, BucketKind::Weight);
+                assert_eq!(bucket.remain, 1000);
+                assert_eq!(bucket.used_ratio(), 5000.0 / 6000.0);
+            }
+            x => panic!("unexpected event {x:?}"),
+        }
+        // 同一时间段内不再重复触发
+        acquire(RateLimitParam::Weight(1)).await.unwrap();
+        assert!(events.try_recv().is_err());
+
+        let snapshot = limits.snapshot().await;
+        let orders: Vec<_> = snapshot
+            .iter()
+            .filter(|x| x.kind == BucketKind::Orders)
+            .collect();
+        assert_eq!(orders.len(), 2);
+        assert!(
+            orders
+                .iter()
+                .all(|x| x.uid.as_deref() == Some("a") && x.remain == x.limit - 1)
+        );
+        let weight = snapshot
+            .iter()
+            .find(|x| x.kind == BucketKind::Weight)
+            .unwrap();
+        assert_eq!((weight.limit, weight.remain), (6000, 999));
+        assert_eq!(weight.interval.duration(), Duration::from_secs(60));
+        assert_eq!(
+            weight.reset_at,
+            clock.system_time() + Duration::from_secs(60)
+        );
     }
 
-    async fn reset_order_sec10_permits(&self) {
-        let mut x = self.inner.write().await;
-        x.order_sec10.reset_permits();
+    #[tokio::test]
+    async fn test_scopes() {
+        let clock = ManualClock::new(START_MS);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        limits
+            .add_scope("scanner", Quota::Share(0.3))
+            .await
+            .unwrap();
+        limits
+            .add_scope(
+                "trader",
+                Quota::Fixed {
+                    weight: 1000,
+                    orders: 50,
+                },
+            )
+            .await
+            .unwrap();
+        // 份额超出上限，或者所有分区的份额之和超出上限
+        let fixed = Quota::Fixed {
+            weight: 7000,
+            orders: 0,
+        };
+        assert!(limits.add_scope("big", fixed).await.is_err());
+        assert!(limits.add_scope("big", Quota::Share(0.6)).await.is_err());
+        assert!(
+            limits
+                .add_scope("scanner", Quota::Share(1.5))
+                .await
+                .is_err()
+        );
+        limits
+            .add_scope("scanner", Quota::Share(0.3))
+            .await
+            .unwrap();
+        let acquire_as =
+            |limit_param: RateLimitParam, uid: &'static str, scope: Option<&'static str>| {
+                let limits = limits.clone();
+                async move {
+                    limits
+                        .acquire_permits(
+                            limit_param,
+                            LimitBucket::Api,
+                            uid,
+                            scope,
+                            RequestPriority::Normal,
+                            Some(Duration::from_millis(50)),
+                            true,
+                        )
+                        .await
+                }
+            };
+        let acquire = |limit_param: RateLimitParam, scope: Option<&'static str>| {
+            acquire_as(limit_param, "", scope)
+        };
+
+        // 分区不能超出自己的份额
+        acquire(RateLimitParam::Weight(1800), Some("scanner"))
+            .await
+            .unwrap();
+        let scanner = acquire(RateLimitParam::Weight(1), Some("scanner"));
+        assert!(advance_waiting(&limits, &clock, scanner).await.is_err());
+        // 未分区的请求不能使用trader预留的1000权重
+        let unscoped = acquire(RateLimitParam::Weight(3201), None);
+        assert!(advance_waiting(&limits, &clock, unscoped).await.is_err());
+        // 等待中的scanner请求不会为自己预留权重而阻塞其它请求
+        let waiting = tokio::spawn(acquire(RateLimitParam::Weight(1800), Some("scanner")));
+        while !limits.inner.read().await.has_waiters() {
+            tokio::task::yield_now().await;
+        }
+        acquire(RateLimitParam::Weight(3200), None).await.unwrap();
+        clock.advance(Duration::from_millis(50));
+        assert!(waiting.await.unwrap().is_err());
+        acquire(RateLimitParam::Order(998), Some("trader"))
+            .await
+            .unwrap();
+        acquire(RateLimitParam::Order(1), Some("trader"))
+            .await
+            .unwrap();
+        // 分区的下单次数按账户计数
+        acquire_as(RateLimitParam::Order(1), "a", Some("trader"))
+            .await
+            .unwrap();
+
+        let stats = limits.scope_stats().await;
+        assert_eq!(stats.len(), 2);
+        let trader = &stats[1];
+        assert_eq!(trader.name, "trader");
+        assert_eq!(
+            (trader.requests, trader.weight, trader.orders),
+            (3, 1000, 3)
+        );
+        let bucket = |kind: BucketKind| trader.buckets.iter().find(|x| x.kind == kind).unwrap();
+        assert_eq!(
+            (
+                bucket(BucketKind::Weight).limit,
+                bucket(BucketKind::Weight).remain
+            ),
+            (1000, 0)
+        );
+        // 每10秒50次，按比例换算为每天80000次
+        let orders = |uid: &str| -> Vec<_> {
+            trader
+                .buckets
+                .iter()
+                .filter(|x| x.kind == BucketKind::Orders && x.uid.as_deref() == Some(uid))
+                .map(|x| (x.limit, x.remain))
+                .collect()
+        };
+        assert_eq!(orders(""), [(50, 48), (80000, 79998)]);
+        assert_eq!(orders("a"), [(50, 49), (80000, 79999)]);
+        assert_eq!(stats[0].weight, 1800);
     }
 
-    async fn reset_order_day1_permits(&self) {
-        let mut x = self.inner.write().await;
-        x.order_day1.reset_permits();
+    #[tokio::test]
+    async fn test_stale_response() {
+        // 23:00:59.500
+        let clock = ManualClock::new(START_MS + 59_500);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        limits
+            .acquire_permits(
+                RateLimitParam::Weight(10),
+                LimitBucket::Api,
+                "",
+                None,
+                RequestPriority::Normal,
+                None,
+                true,
+            )
+            .await
+            .unwrap();
+        let used = |n: u32| {
+            UsedPermits::from_counters(&HashMap::from([("x-mbx-used-weight-1m".to_string(), n)]))
+        };
+
+        // 响应在重置前发出，但到达时时钟已经进入了下一分钟，不能用上一分钟的已使用值更新
+        clock.advance(Duration::from_millis(700));
+        limits
+            .set_permits("", "Thu, 24 Aug 2023 23:00:59 GMT".to_string(), used(5995))
+            .await;
+        assert_eq!(limits.inner.read().await.weights[0].remain, 6000);
+
+        // 重置后发出的响应正常更新
+        limits
+            .set_permits("", "Thu, 24 Aug 2023 23:01:00 GMT".to_string(), used(100))
+            .await;
+        assert_eq!(limits.inner.read().await.weights[0].remain, 6000 - 100);
     }
 
-    async fn reset_raw_request_permits(&self) {
-        let mut x = self.inner.write().await;
-        x.raw_requests.reset_permits();
+    #[tokio::test]
+    async fn test_exceeds_limit() {
+        let clock = ManualClock::new(1_692_918_000_000);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let acquire = |n: u32, bucket: LimitBucket| {
+            let limits = limits.clone();
+            async move {
+                limits
+                    .acquire_permits(
+                        RateLimitParam::Weight(n),
+                        bucket,
+                        "",
+                        None,
+                        RequestPriority::Normal,
+                        None,
+                        true,
+                    )
+                    .await
+            }
+        };
+
+        // 超出上限的请求立即返回错误，不等待也不预留权重
+        assert!(matches!(
+            acquire(6001, LimitBucket::Api).await,
+            Err(BiAnApiError::ArgumentError(_))
+        ));
+        assert!(matches!(
+            acquire(12001, LimitBucket::SapiIp).await,
+            Err(BiAnApiError::ArgumentError(_))
+        ));
+        acquire(6000, LimitBucket::Api).await.unwrap();
+
+        // 等待中的请求因限速规则变小而无法满足时返回错误，不再阻塞后面的请求
+        let large = tokio::spawn(acquire(3000, LimitBucket::Api));
+        while !limits.inner.read().await.has_waiters() {
+            tokio::task::yield_now().await;
+        }
+        let small = tokio::spawn(acquire(10, LimitBucket::Api));
+        tokio::task::yield_now().await;
+        {
+            let mut inner = limits.inner.write().await;
+            inner.weights[0].rate_limit.limit = 1000;
+        }
+        clock.advance(Duration::from_secs(60));
+        limits.inner.write().await.dispatch();
+        assert!(matches!(
+            large.await.unwrap(),
+            Err(BiAnApiError::ArgumentError(_))
+        ));
+        small.await.unwrap().unwrap();
     }
-}
 
-#[cfg(test)]
-mod tt {
-    use chrono_ext::ParseDateTimeExt;
+    #[tokio::test]
+    async fn test_manual_clock() {
+        // 2023-08-24 23:00:00 UTC
+        let clock = ManualClock::new(1_692_918_000_000);
+        let limits = RestApiRateLimits::new(Arc::new(clock.clone())).await;
+        let acquire = |timeout: Option<Duration>| {
+            let limits = limits.clone();
+            async move {
+                limits
+                    .acquire_permits(
+                        RateLimitParam::Order(1),
+                        LimitBucket::Api,
+                        "a",
+                        None,
+                        RequestPriority::Normal,
+                        timeout,
+                        true,
+                    )
+                    .await
+            }
+        };
+
+        // 模拟2小时的下单：每10秒用完100次下单次数，之后的请求等待超时
+        for _ in 0..720 {
+            for _ in 0..100 {
+                acquire(None).await.unwrap();
+            }
+            let waiting = tokio::spawn(acquire(Some(Duration::from_secs(5))));
+            while !limits.inner.read().await.has_waiters() {
+                tokio::task::yield_now().await;
+            }
+            clock.advance(Duration::from_secs(5));
+            assert!(matches!(
+                waiting.await.unwrap(),
+                Err(BiAnApiError::RateLimitTimeout(_))
+            ));
+            clock.advance(Duration::from_secs(5));
+        }
+
+        // 每天的下单次数在UTC零点被重置，只计入零点之后的1小时
+        let orders: Vec<_> = limits
+            .snapshot()
+            .await
+            .into_iter()
+            .filter(|x| x.kind == BucketKind::Orders)
+            .collect();
+        assert_eq!(orders[0].remain, 100);
+        assert_eq!(orders[1].remain, 160000 - 36000);
+        assert_eq!(
+            orders[1].reset_at,
+            clock.system_time() + Duration::from_secs(23 * 3600)
+        );
+
+        // 封禁按时钟计时
+        limits.ban(418, None).await;
+        let waiting = tokio::spawn({
+            let limits = limits.clone();
+            async move { limits.wait_ban(true).await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+        clock.advance(Duration::from_secs(60));
+        waiting.await.unwrap().unwrap();
+        assert!(limits.banned_until().is_none());
+    }
 
     #[tokio::test]
     async fn t() {
@@ -1,37 +1,119 @@
 use crate::{
-    ExchangeInfo, SymbolInfo,
+    ExchangeInfo,
     client::rate_limit::RateLimitParam,
     errors::{BiAnApiError, BiAnResult, MethodError},
-    utils::ExchangeInfoExt,
 };
+use arc_swap::ArcSwap;
 use ba_global::REST_BASE_URL;
 use ba_types::BadRequest;
 use reqwest::{Url, header};
 use serde::Serialize;
 use std::{
+    collections::HashMap,
     fmt::Debug,
     str::FromStr,
-    sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
 };
-use tokio::time;
-use tracing::{error, warn};
+use tokio::{
+    sync::broadcast,
+    task::AbortHandle,
+    time::{self, Instant},
+};
+use tracing::{debug, error, warn};
 
 use super::{
-    params::{CheckType, Param},
-    rate_limit::RestApiRateLimits,
+    clock::{Clock, SystemClock},
+    clock_sync::ClockSync,
+    endpoint::Endpoint,
+    exchange_info::{
+        ExchangeInfoCache, ExchangeInfoCachePolicy, ExchangeInfoEvent, ExchangeInfoQuery,
+        SharedExchangeInfo, SymbolInfoRef,
+    },
+    hosts::{HostStats, Hosts},
+    key_pool::{KeyPool, PoolKey},
+    params::{CheckType, PPing, Param, RecvWindow, SignOptions, TimestampUnit},
+    rate_limit::{
+        BucketSnapshot, DEFAULT_UID, LimitBucket, Quota, RateLimitEvent, RequestPriority,
+        RestApiRateLimits, ScopeStats, Threshold, UsedPermits,
+    },
+    retry::{ExponentialBackoff, FailureKind, RetryContext, RetryPolicy},
+    secret::Secret,
+    signer::{Signer, sec_key_signer},
+    weight::WeightTable,
 };
 use crate::ApiSecKey;
 
 /// REST响应体
 pub(crate) type RespBody = String;
 
+/// 每次同步服务器时间时的采样次数，使用往返时间最短的一次
+const CLOCK_SYNC_SAMPLES: usize = 3;
+
+/// REST响应的元信息，从响应状态码和响应头中提取
+#[derive(Debug, Clone, Default)]
+pub struct ResponseMeta {
+    /// HTTP状态码
+    pub status: u16,
+    /// x-mbx-used-weight-1m
+    pub used_weight_1m: Option<u32>,
+    /// x-mbx-order-count-10s
+    pub order_count_10s: Option<u32>,
+    /// x-mbx-order-count-1d
+    pub order_count_1d: Option<u32>,
+    /// 所有的限速计数器，包括x-mbx-used-weight-*、x-mbx-order-count-*、x-sapi-used-*，
+    /// key为小写的响应头名称
+    pub rate_limit_counters: HashMap<String, u32>,
+    /// 服务器时间，例如"Fri, 25 Aug 2023 10:12:14 GMT"
+    pub date: Option<String>,
+    /// 请求的唯一ID(x-mbx-uuid)
+    pub uuid: Option<String>,
+    /// 被限速或被封禁时，需要等待多久才能再次请求
+    pub retry_after: Option<Duration>,
+    /// 从发送请求(最后一次重试)到读取完响应体所花的时间
+    pub latency: Duration,
+}
+
+impl ResponseMeta {
+    fn from_headers(status: u16, head: &header::HeaderMap) -> Self {
+        let get = |key: &str| {
+            head.get(key)
+                .and_then(|v| v.to_str().ok())
+                .map(String::from)
+        };
+        let rate_limit_counters: HashMap<String, u32> = head
+            .iter()
+            .filter(|(k, _)| {
+                let k = k.as_str();
+                k.starts_with("x-mbx-used-weight")
+                    || k.starts_with("x-mbx-order-count")
+                    || k.starts_with("x-sapi-used")
+            })
+            .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.parse().ok()?)))
+            .collect();
+
+        Self {
+            status,
+            used_weight_1m: rate_limit_counters.get("x-mbx-used-weight-1m").copied(),
+            order_count_10s: rate_limit_counters.get("x-mbx-order-count-10s").copied(),
+            order_count_1d: rate_limit_counters.get("x-mbx-order-count-1d").copied(),
+            rate_limit_counters,
+            date: get("date"),
+            uuid: get("x-mbx-uuid"),
+            retry_after: get("retry-after")
+                .and_then(|x| x.parse::<u64>().ok())
+                .map(Duration::from_secs),
+            latency: Duration::ZERO,
+        }
+    }
+}
+
 /// 币安只支持GET POST PUT和DELETE四种HTTP方法
 /// ```rust
 /// assert_eq!(RestMethod::from_str('get'), RestMethod::Get);
 /// assert_eq!(RestMethod::from_str('GET'), RestMethod::Get);
 /// ```
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RestMethod {
     Get,
     Post,
@@ -59,15 +141,471 @@ impl FromStr for RestMethod {
     }
 }
 
+/// 发送请求所用的账户
+struct RequestKey<'a> {
+    /// 下单次数限速所属的账户
+    uid: &'a str,
+    api_sec_key: &'a ApiSecKey,
+    signer: &'a dyn Signer,
+}
+
+/// 币安现货测试网的REST地址
+pub const REST_TESTNET_URL: &str = "https://testnet.binance.vision";
+
 #[allow(dead_code)]
 /// 已建立好的Http连接客户端(reqwest::Client)
 #[derive(Clone)]
 pub struct RestConn {
     conn: reqwest::Client,
     api_sec_key: ApiSecKey,
-    base_url: Url,
+    /// 签名请求的签名方式，默认使用api_sec_key签名
+    signer: Arc<dyn Signer>,
+    /// 多个账户的key
+    key_pool: Arc<KeyPool>,
+    /// 通过`with_key()`选择的KeyPool中的key
+    key: Option<Arc<str>>,
+    /// 所有等价的REST地址及其健康统计，每次请求选择最健康且延迟最低的地址
+    hosts: Hosts,
     rate_limit: RestApiRateLimits,
-    exchange_info: Arc<Option<ExchangeInfo>>,
+    /// 所有克隆共享的exchange_info，惰性加载模式下在首次使用时或在后台加载
+    exchange_info: SharedExchangeInfo,
+    /// exchange_info的查询范围
+    pub(crate) exchange_info_query: Arc<ExchangeInfoQuery>,
+    /// exchange_info的缓存
+    pub(crate) exchange_info_cache: ExchangeInfoCache,
+    /// 签名请求默认的recvWindow
+    recv_window: RecvWindow,
+    /// 签名请求中timestamp的单位
+    timestamp_unit: TimestampUnit,
+    /// 本地时间与服务器时间的同步状态，签名请求的timestamp将根据其校正
+    clock: ClockSync,
+    /// 请求失败时的重试策略
+    retry_policy: Arc<dyn RetryPolicy>,
+    /// 收到418或429后，是否等待封禁结束再发送请求
+    wait_on_ban: bool,
+    /// 签名的POST/PUT/DELETE请求是否将参数放在请求体中
+    signed_body: bool,
+    /// 通过`with_priority()`指定的请求优先级
+    priority: Option<RequestPriority>,
+    /// 等待限速权重的超时时间
+    rate_limit_timeout: Option<Duration>,
+    /// 通过`scoped()`指定的限速分区
+    scope: Option<Arc<str>>,
+    /// 覆盖内置权重的权重表，所有克隆共享
+    weights: Arc<ArcSwap<WeightTable>>,
+    /// 后台任务，后台任务自身持有的克隆为None，因此所有其它克隆被drop后后台任务即被中止
+    tasks: Option<Arc<BackgroundTasks>>,
+}
+
+/// RestConn的后台任务(刷新exchange_info、同步时钟等)，被drop时中止所有后台任务
+#[derive(Default)]
+struct BackgroundTasks(Mutex<Vec<AbortHandle>>);
+
+impl Drop for BackgroundTasks {
+    fn drop(&mut self) {
+        for task in self.0.get_mut().unwrap().drain(..) {
+            task.abort();
+        }
+    }
+}
+
+/// RestConn的构建器
+///
+/// ```rust
+/// // 连接到现货测试网，且启动时不加载exchange_info
+/// let rest_conn = RestConn::builder(api_sec_key)
+///     .testnet()
+///     .proxy("http://127.0.0.1:8118")
+///     .request_timeout(Duration::from_secs(10))
+///     .load_exchange_info(false)
+///     .build()
+///     .await?;
+///
+/// // 连接到本地的模拟服务，exchange_info在后台加载，加载成功前使用默认的限速规则
+/// let rest_conn = RestConn::builder(api_sec_key)
+///     .base_url("http://127.0.0.1:8080")
+///     .lazy(true)
+///     .build()
+///     .await?;
+/// ```
+pub struct RestConnBuilder {
+    api_sec_key: ApiSecKey,
+    signer: Option<Arc<dyn Signer>>,
+    key_pool: KeyPool,
+    base_urls: Vec<String>,
+    probe_hosts: Option<Duration>,
+    proxy: Option<String>,
+    connect_timeout: Duration,
+    request_timeout: Option<Duration>,
+    recv_window: RecvWindow,
+    timestamp_unit: TimestampUnit,
+    clock_sync: Option<Duration>,
+    clock: Arc<dyn Clock>,
+    retry_policy: Arc<dyn RetryPolicy>,
+    wait_on_ban: bool,
+    signed_body: bool,
+    rate_limit_timeout: Option<Duration>,
+    rate_limit_thresholds: Vec<Threshold>,
+    weight_table: WeightTable,
+    #[cfg(feature = "shared-rate-limit")]
+    shared_rate_limit: Option<std::path::PathBuf>,
+    load_exchange_info: bool,
+    lazy: bool,
+    exchange_info_refresh: Option<Duration>,
+    exchange_info_query: ExchangeInfoQuery,
+    exchange_info_cache: ExchangeInfoCachePolicy,
+}
+
+impl RestConnBuilder {
+    pub fn new(api_sec_key: ApiSecKey) -> Self {
+        Self {
+            api_sec_key,
+            signer: None,
+            key_pool: KeyPool::default(),
+            base_urls: vec![REST_BASE_URL.to_string()],
+            probe_hosts: None,
+            proxy: None,
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: None,
+            recv_window: RecvWindow::default(),
+            timestamp_unit: TimestampUnit::default(),
+            clock_sync: None,
+            clock: Arc::new(SystemClock),
+            retry_policy: Arc::new(ExponentialBackoff::default()),
+            wait_on_ban: true,
+            signed_body: false,
+            rate_limit_timeout: None,
+            rate_limit_thresholds: vec![],
+            weight_table: WeightTable::default(),
+            #[cfg(feature = "shared-rate-limit")]
+            shared_rate_limit: None,
+            load_exchange_info: true,
+            lazy: false,
+            exchange_info_refresh: None,
+            exchange_info_query: ExchangeInfoQuery::default(),
+            exchange_info_cache: ExchangeInfoCachePolicy::default(),
+        }
+    }
+
+    /// REST请求的基础地址，默认为`ba_global::REST_BASE_URL`，
+    /// 可设置为api1/api2/api3等备用地址，或者本地的模拟服务地址
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_urls = vec![base_url.into()];
+        self
+    }
+
+    /// 多个等价的REST基础地址，例如`REST_BASE_URL`和`REST_BACKUP_URLS`，
+    /// 每次请求选择最健康且延迟最低的地址，连接失败或5xx时，重试将自动切换到其它地址
+    /// ```rust
+    /// let rest_conn = RestConn::builder(api_sec_key)
+    ///     .base_urls([REST_BASE_URL].into_iter().chain(REST_BACKUP_URLS))
+    ///     .probe_hosts(Duration::from_secs(60))
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn base_urls<I, S>(mut self, base_urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.base_urls = base_urls.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// 构建时通过ping测量一次所有地址的延迟，之后在后台每隔interval测量一次，默认不测量
+    pub fn probe_hosts(mut self, interval: Duration) -> Self {
+        self.probe_hosts = Some(interval);
+        self
+    }
+
+    /// 签名请求的签名方式，默认使用ApiSecKey签名，
+    /// 使用其它Signer时，ApiSecKey只需提供api_key，私钥可以不在当前进程中
+    /// ```rust
+    /// let rest_conn = RestConn::builder(ApiSecKey::new(Some(api_key), None))
+    ///     .signer(UnixSocketSigner::new("/run/signer.sock"))
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn signer(mut self, signer: impl Signer + 'static) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// 签名使用的secret key，保存在`Secret`中，drop时清零内存，此时ApiSecKey只需提供api_key，
+    /// ApiSecKey中的secret key是普通的String，不会被清零
+    /// ```rust
+    /// let rest_conn = RestConn::builder(ApiSecKey::new(Some(api_key), None))
+    ///     .sec_key(sec_key)
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn sec_key(mut self, sec_key: impl Into<Secret>) -> Self {
+        self.signer = Some(sec_key_signer(sec_key));
+        self
+    }
+
+    /// 多个账户的key，通过`RestConn::with_key()`选择发送请求的账户，
+    /// 所有账户共享IP的权重限速，但各自计算下单次数
+    pub fn key_pool(mut self, key_pool: KeyPool) -> Self {
+        self.key_pool = key_pool;
+        self
+    }
+
+    /// 使用现货测试网的地址
+    pub fn testnet(self) -> Self {
+        self.base_url(REST_TESTNET_URL)
+    }
+
+    /// 代理地址，例如"http://127.0.0.1:8118"、"socks5://127.0.0.1:1080"
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// 建立连接过程的超时时间，默认5秒
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// 整个请求(从建立连接到读取完响应)的超时时间，默认不超时
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// 签名请求默认的recvWindow，默认5000毫秒，可以是毫秒数或者精确到微秒的`RecvWindow`，
+    /// 参数通过`Param::recv_window()`指定了recvWindow时，使用参数的值
+    pub fn recv_window(mut self, recv_window: impl Into<RecvWindow>) -> Self {
+        self.recv_window = recv_window.into();
+        self
+    }
+
+    /// 签名请求中timestamp的单位，默认为毫秒
+    pub fn timestamp_unit(mut self, unit: TimestampUnit) -> Self {
+        self.timestamp_unit = unit;
+        self
+    }
+
+    /// 构建时同步一次服务器时间，之后在后台每隔interval同步一次，
+    /// 签名请求的timestamp将使用校正后的时间，默认不同步(收到-1021错误时仍会同步一次)
+    pub fn clock_sync(mut self, interval: Duration) -> Self {
+        self.clock_sync = Some(interval);
+        self
+    }
+
+    /// 本地时间的来源，限速、klines的完成检查以及签名请求的timestamp都使用该时钟，
+    /// 默认为系统时间，测试时可以使用`ManualClock`
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// 剩余权重不足时，等待权重的超时时间，超时后返回`BiAnApiError::RateLimitTimeout`，
+    /// 默认一直等待
+    pub fn rate_limit_timeout(mut self, timeout: Duration) -> Self {
+        self.rate_limit_timeout = Some(timeout);
+        self
+    }
+
+    /// 限速的使用率阈值，已使用的比例越过阈值时发送`RateLimitEvent::ThresholdCrossed`事件，
+    /// 可据此在被限速之前主动降低请求频率，参考`Threshold`
+    pub fn rate_limit_thresholds(
+        mut self,
+        thresholds: impl IntoIterator<Item = Threshold>,
+    ) -> Self {
+        self.rate_limit_thresholds = thresholds.into_iter().collect();
+        self
+    }
+
+    /// 覆盖内置权重的权重表，表中没有的接口继续使用内置的权重，参考`WeightTable`
+    ///
+    /// ```rust
+    /// let table = WeightTable::from_json(&std::fs::read_to_string("weights.json")?)?;
+    /// let rest_conn = RestConn::builder(api_sec_key)
+    ///     .weight_table(table)
+    ///     .build()
+    ///     .await?;
+    /// ```
+    pub fn weight_table(mut self, table: WeightTable) -> Self {
+        self.weight_table = table;
+        self
+    }
+
+    /// 与同一台机器上使用同一文件的其它进程共享IP的权重和请求次数，
+    /// 所有进程的请求都从同一份预算中扣除，并将响应头中的已使用值汇总到该预算中，
+    /// 默认只在进程内限速
+    ///
+    /// ```rust
+    /// let rest_conn = RestConn::builder(api_sec_key)
+    ///     .shared_rate_limit("/dev/shm/ba_api_rate_limit")
+    ///     .build()
+    ///     .await?;
+    /// ```
+    #[cfg(feature = "shared-rate-limit")]
+    pub fn shared_rate_limit(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.shared_rate_limit = Some(path.into());
+        self
+    }
+
+    /// 使用指数退避的重试策略，最多重试retry_times次，首次重试前等待retry_interval，之后每次加倍，
+    /// 默认最多重试5次，首次等待0.5秒，参考`ExponentialBackoff`
+    pub fn retry(self, retry_times: u32, retry_interval: Duration) -> Self {
+        self.retry_policy(ExponentialBackoff::new(retry_times, retry_interval))
+    }
+
+    /// 请求失败时的重试策略，不重试时设置为`NoRetry`
+    pub fn retry_policy(mut self, policy: impl RetryPolicy + 'static) -> Self {
+        self.retry_policy = Arc::new(policy);
+        self
+    }
+
+    /// 收到418(IP被封禁)或429(请求过多)后，根据Retry-After记录封禁状态，
+    /// 封禁结束前所有克隆的/api和/sapi请求都将暂停，
+    /// 设置为false时不等待，而是立即返回`BiAnApiError::Banned`，默认等待
+    pub fn wait_on_ban(mut self, wait: bool) -> Self {
+        self.wait_on_ban = wait;
+        self
+    }
+
+    /// 签名的POST/PUT/DELETE请求是否将参数(包括signature)以
+    /// application/x-www-form-urlencoded的形式放在请求体中，
+    /// 避免签名出现在代理日志中，以及参数过多时超出URL的长度限制，GET请求总是使用URL参数，默认关闭
+    pub fn signed_body(mut self, enable: bool) -> Self {
+        self.signed_body = enable;
+        self
+    }
+
+    /// 启动时是否加载exchange_info并根据其更新限速规则，默认加载
+    pub fn load_exchange_info(mut self, load: bool) -> Self {
+        self.load_exchange_info = load;
+        self
+    }
+
+    /// 惰性加载模式，默认关闭
+    ///
+    /// 开启后，构建时不等待exchange_info，而是在后台加载(失败时不断重试)，
+    /// 首次下单时如果仍未加载成功，也会尝试加载一次。
+    /// 加载成功前，symbol_info()返回None，且使用默认的限速规则
+    pub fn lazy(mut self, lazy: bool) -> Self {
+        self.lazy = lazy;
+        self
+    }
+
+    /// 在后台每隔interval刷新一次exchange_info，并在刷新后根据其中的rateLimits更新限速规则，
+    /// 默认不刷新
+    pub fn exchange_info_refresh(mut self, interval: Duration) -> Self {
+        self.exchange_info_refresh = Some(interval);
+        self
+    }
+
+    /// exchange_info的查询范围，默认为报价资产是USDT的现货交易对，
+    /// 只有在该范围内的交易对，下单时才会自动调整价格和数量
+    pub fn exchange_info_query(mut self, query: ExchangeInfoQuery) -> Self {
+        self.exchange_info_query = query;
+        self
+    }
+
+    /// exchange_info的缓存策略(缓存位置、有效期)，默认缓存在应用目录下，有效期为半小时，
+    /// 只读文件系统中可设置为只缓存在内存中或不缓存
+    pub fn exchange_info_cache(mut self, policy: ExchangeInfoCachePolicy) -> Self {
+        self.exchange_info_cache = policy;
+        self
+    }
+
+    /// 构建RestConn
+    ///
+    /// 代理地址或基础地址无效时返回错误，
+    /// 非惰性加载模式下，获取exchange_info失败时也返回错误
+    pub async fn build(self) -> BiAnResult<RestConn> {
+        // 设置建立连接过程的超时时间
+        // 空闲连接永不断开(一直保持长连接)
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .pool_idle_timeout(None);
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(prx) = self.proxy {
+            builder = builder.proxy(reqwest::Proxy::all(prx)?);
+        }
+        let conn = builder.build()?;
+
+        if self.base_urls.is_empty() {
+            return Err(BiAnApiError::ArgumentError("no base url".into()));
+        }
+        let base_urls = self
+            .base_urls
+            .iter()
+            .map(|x| {
+                Url::parse(x).map_err(|e| {
+                    BiAnApiError::ArgumentError(format!("invalid base url `{x}': {e}"))
+                })
+            })
+            .collect::<BiAnResult<Vec<_>>>()?;
+
+        let signer = self
+            .signer
+            .unwrap_or_else(|| Arc::new(self.api_sec_key.clone()));
+        let rate_limit = RestApiRateLimits::new(self.clock.clone()).await;
+        rate_limit.set_thresholds(self.rate_limit_thresholds).await;
+        #[cfg(feature = "shared-rate-limit")]
+        if let Some(path) = &self.shared_rate_limit {
+            rate_limit.share(path).await?;
+        }
+        let rest_conn = RestConn {
+            conn,
+            api_sec_key: self.api_sec_key,
+            signer,
+            key_pool: Arc::new(self.key_pool),
+            key: None,
+            hosts: Hosts::new(base_urls),
+            rate_limit,
+            exchange_info: SharedExchangeInfo::default(),
+            exchange_info_query: Arc::new(self.exchange_info_query),
+            exchange_info_cache: ExchangeInfoCache::new(
+                self.exchange_info_cache,
+                self.clock.clone(),
+            ),
+            recv_window: self.recv_window,
+            timestamp_unit: self.timestamp_unit,
+            clock: ClockSync::with_clock(self.clock),
+            retry_policy: self.retry_policy,
+            wait_on_ban: self.wait_on_ban,
+            signed_body: self.signed_body,
+            priority: None,
+            rate_limit_timeout: self.rate_limit_timeout,
+            scope: None,
+            weights: Arc::new(ArcSwap::from_pointee(self.weight_table)),
+            tasks: Some(Arc::default()),
+        };
+
+        if let Some(interval) = self.probe_hosts {
+            rest_conn.probe_hosts().await;
+            rest_conn.spawn_host_prober(interval);
+        }
+
+        if let Some(interval) = self.clock_sync {
+            if let Err(e) = rest_conn.sync_clock().await {
+                warn!("sync clock failed: {}", e);
+            }
+            rest_conn.spawn_clock_syncer(interval);
+        }
+
+        if self.load_exchange_info {
+            if self.lazy {
+                rest_conn.spawn_exchange_info_loader();
+            } else {
+                rest_conn.ensure_exchange_info().await?;
+            }
+        }
+
+        if let Some(interval) = self.exchange_info_refresh {
+            rest_conn.spawn_exchange_info_refresher(interval);
+        }
+
+        Ok(rest_conn)
+    }
 }
 
 #[allow(dead_code)]
@@ -78,43 +616,149 @@ impl RestConn {
     /// let api_sec_key = ApiSecKey::new(api_key, sec_key);
     /// let rest_conn = RestConn::new(api_sec_key, Some("http://127.0.0.1:8118".to_string()));
     ///```
+    ///
+    /// 获取exchange_info失败时不会panic，而是转为在后台继续加载，
+    /// 但代理地址无效时会panic，如需处理该错误，使用`try_new()`
     pub async fn new(api_sec_key: ApiSecKey, proxy: Option<String>) -> RestConn {
-        // 设置建立连接过程的超时时间5秒
-        // 空闲连接永不断开(一直保持长连接)
-        let builder = reqwest::Client::builder()
-            .connect_timeout(time::Duration::from_secs(5))
-            .pool_idle_timeout(None);
-        // let url = *REST_BASE_URL;
+        let rest_conn = Self::try_new(api_sec_key, proxy)
+            .await
+            .expect("build RestConn failed");
+        if let Err(e) = rest_conn.ensure_exchange_info().await {
+            error!(
+                "get exchange_info failed: {}, keep loading in background",
+                e
+            );
+            rest_conn.spawn_exchange_info_loader();
+        }
+        rest_conn
+    }
 
-        let conn = match proxy {
-            Some(prx) => {
-                let p = reqwest::Proxy::all(prx).expect("proxy error!");
-                builder.proxy(p).build().unwrap()
-            }
-            None => builder.build().unwrap(),
-        };
+    /// 创建RestConn，但不加载exchange_info(首次下单时或调用`ensure_exchange_info()`时加载)，
+    /// 代理地址无效时返回错误
+    pub async fn try_new(api_sec_key: ApiSecKey, proxy: Option<String>) -> BiAnResult<RestConn> {
+        let mut builder = Self::builder(api_sec_key).load_exchange_info(false);
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+        builder.build().await
+    }
 
-        let mut rest_conn = RestConn {
-            conn,
-            api_sec_key,
-            base_url: Url::parse(REST_BASE_URL).unwrap(),
-            rate_limit: RestApiRateLimits::new().await,
-            exchange_info: Arc::new(None),
-        };
+    /// 通过构建器创建RestConn，可设置基础地址、代理、超时时间、recvWindow、重试规则等
+    pub fn builder(api_sec_key: ApiSecKey) -> RestConnBuilder {
+        RestConnBuilder::new(api_sec_key)
+    }
+
+    /// 返回使用指定优先级获取限速权重的克隆，与原RestConn共享连接、限速规则和exchange_info，
+    /// 默认下单、撤单等非GET请求为High，其它请求为Normal
+    pub fn with_priority(&self, priority: RequestPriority) -> RestConn {
+        RestConn {
+            priority: Some(priority),
+            ..self.clone()
+        }
+    }
+
+    /// 返回使用指定recvWindow的克隆，与原RestConn共享连接、限速规则和exchange_info
+    /// ```rust
+    /// // 下单时使用较小的recvWindow
+    /// rest_conn.with_recv_window(1000).order(...).await?;
+    /// // 查询历史数据时使用较大的recvWindow
+    /// rest_conn.with_recv_window(60000).get_all_orders(...).await?;
+    /// ```
+    pub fn with_recv_window(&self, recv_window: impl Into<RecvWindow>) -> RestConn {
+        RestConn {
+            recv_window: recv_window.into(),
+            ..self.clone()
+        }
+    }
 
-        match rest_conn.exchange_info().await {
-            Ok(exchange_info) => rest_conn.exchange_info = Arc::new(Some(exchange_info)),
-            Err(e) => error!("get exchange_info failed: {}", e),
+    /// 返回使用KeyPool中名为name的key发送请求的克隆，与原RestConn共享连接和限速规则，
+    /// 下单请求将使用该账户的下单次数限速，KeyPool中没有该key时返回错误
+    pub fn with_key(&self, name: &str) -> BiAnResult<RestConn> {
+        if self.key_pool.get(name).is_none() {
+            return Err(BiAnApiError::ArgumentError(format!(
+                "key `{name}' not found in key pool"
+            )));
         }
+        Ok(RestConn {
+            key: Some(Arc::from(name)),
+            ..self.clone()
+        })
+    }
 
-        let ex = rest_conn.get_exchange_info().unwrap();
-        rest_conn.rate_limit.update(ex).await;
+    /// 返回在限速分区name中获取权重的克隆，与原RestConn共享连接和限速规则，
+    /// 分区只能使用quota份额内的/api/*权重和下单次数，其它请求也不能使用该分区的份额，
+    /// 同名的分区已存在时更新其份额，所有分区的份额之和超出限速的上限时返回错误
+    /// ```rust
+    /// // 行情扫描最多使用30%的权重，剩余的权重留给订单管理
+    /// let scanner = rest_conn.scoped("scanner", Quota::Share(0.3)).await?;
+    /// let trader = rest_conn.scoped("trader", Quota::Fixed { weight: 1000, orders: 50 }).await?;
+    /// ```
+    pub async fn scoped(&self, name: &str, quota: Quota) -> BiAnResult<RestConn> {
+        self.rate_limit.add_scope(name, quota).await?;
+        Ok(RestConn {
+            scope: Some(Arc::from(name)),
+            ..self.clone()
+        })
+    }
 
-        // if let Ok(exchange_info) = rest_conn.exchange_info(None).await {
-        //     rest_conn.exchange_info = Arc::new(Some(exchange_info));
-        // };
+    /// 所有限速分区的使用统计
+    pub async fn scope_stats(&self) -> Vec<ScopeStats> {
+        self.rate_limit.scope_stats().await
+    }
 
-        rest_conn
+    /// KeyPool中所有key的名称
+    pub fn key_names(&self) -> Vec<String> {
+        self.key_pool.names().map(String::from).collect()
+    }
+
+    /// 选择发送请求的账户
+    ///
+    /// 通过`with_key()`选择了key时使用该key，KeyPool中没有该key时返回错误；
+    /// 否则不需签名的行情请求从KeyPool中自动选择剩余下单次数最多的key以分摊请求，
+    /// 签名请求(下单、查询账户和订单等)使用RestConn自身的api_sec_key，
+    /// 以免查询到与下单账户不同的账户的数据
+    async fn request_key(&self, check_type: CheckType) -> BiAnResult<RequestKey<'_>> {
+        if let Some(name) = &self.key {
+            let key = self.pool_key(name)?;
+            return Ok(RequestKey {
+                uid: &key.name,
+                api_sec_key: &key.api_sec_key,
+                signer: key.signer.as_ref(),
+            });
+        }
+        if matches!(check_type, CheckType::MarketData | CheckType::None)
+            && let Some(uid) = self.rate_limit.least_used_uid(self.key_pool.names()).await
+            && let Some(key) = self.key_pool.get(uid)
+        {
+            return Ok(RequestKey {
+                uid: &key.name,
+                api_sec_key: &key.api_sec_key,
+                signer: key.signer.as_ref(),
+            });
+        }
+        Ok(RequestKey {
+            uid: DEFAULT_UID,
+            api_sec_key: &self.api_sec_key,
+            signer: self.signer.as_ref(),
+        })
+    }
+
+    fn pool_key(&self, name: &str) -> BiAnResult<&PoolKey> {
+        self.key_pool.get(name).ok_or_else(|| {
+            BiAnApiError::ArgumentError(format!("key `{name}' not found in key pool"))
+        })
+    }
+
+    /// 签名请求使用的api_sec_key和签名方式，通过`with_key()`选择了key时为该key的
+    #[cfg(feature = "websocket")]
+    pub(crate) fn signing_key(&self) -> BiAnResult<(ApiSecKey, Arc<dyn Signer>)> {
+        match &self.key {
+            Some(name) => {
+                let key = self.pool_key(name)?;
+                Ok((key.api_sec_key.clone(), key.signer.clone()))
+            }
+            None => Ok((self.api_sec_key.clone(), self.signer.clone())),
+        }
     }
 
     /// 当前连接使用的api sec key
@@ -122,28 +766,254 @@ impl RestConn {
         self.api_sec_key.clone()
     }
 
-    /// 更新exchange_info信息
-    pub async fn update_exchange_info(&mut self) -> BiAnResult<()> {
-        match self.exchange_info().await {
-            Ok(exchange_info) => {
-                self.exchange_info = Arc::new(Some(exchange_info));
-                Ok(())
+    /// 跳过缓存获取最新的exchange_info信息，并根据其中的rateLimits更新限速规则
+    ///
+    /// 更新后，所有克隆出来的RestConn都将看到新的exchange_info
+    pub async fn update_exchange_info(&self) -> BiAnResult<()> {
+        let exchange_info = self.exchange_info_fresh(&self.exchange_info_query).await?;
+        self.apply_exchange_info(exchange_info).await;
+        Ok(())
+    }
+
+    async fn apply_exchange_info(&self, exchange_info: ExchangeInfo) {
+        self.rate_limit.update(&exchange_info).await;
+        self.exchange_info.store(exchange_info);
+    }
+
+    /// 确保exchange_info已经加载，如果尚未加载，则立即加载并根据其更新限速规则
+    pub async fn ensure_exchange_info(&self) -> BiAnResult<Arc<ExchangeInfo>> {
+        if let Some(exchange_info) = self.exchange_info.get() {
+            return Ok(exchange_info);
+        }
+
+        let _guard = self.exchange_info.lock_load().await;
+        // 等待锁期间可能已经被其它任务加载
+        if let Some(exchange_info) = self.exchange_info.get() {
+            return Ok(exchange_info);
+        }
+        // 首次加载时可以使用缓存
+        let exchange_info = self.exchange_info().await?;
+        self.apply_exchange_info(exchange_info).await;
+        self.exchange_info
+            .get()
+            .ok_or_else(|| BiAnApiError::Unknown("exchange_info missing after update".into()))
+    }
+
+    /// 在后台运行task，task得到的RestConn不会阻止后台任务被中止：
+    /// 除后台任务之外的所有克隆都被drop后，后台任务即被中止，连接池随之释放
+    fn spawn_background<F>(&self, task: impl FnOnce(RestConn) -> F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let Some(tasks) = &self.tasks else {
+            // 后台任务不会再创建后台任务
+            warn!("spawn background task from a background task is not supported");
+            return;
+        };
+        let rest_conn = RestConn {
+            tasks: None,
+            ..self.clone()
+        };
+        let handle = tokio::spawn(task(rest_conn));
+        tasks.0.lock().unwrap().push(handle.abort_handle());
+    }
+
+    /// 在后台每隔interval刷新一次exchange_info
+    fn spawn_exchange_info_refresher(&self, interval: Duration) {
+        self.spawn_background(move |rest_conn| async move {
+            let rest_conn = rest_conn.with_priority(RequestPriority::Low);
+            let mut ticker = time::interval_at(time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = rest_conn.update_exchange_info().await {
+                    warn!("refresh exchange_info failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 通过ping测量所有地址的延迟，并更新其健康统计，处于封禁状态时不测量
+    pub async fn probe_hosts(&self) {
+        for idx in 0..self.hosts.len() {
+            // 封禁期间继续请求会延长封禁时间
+            if let Some(until) = self.rate_limit.banned_until() {
+                debug!("skip probing hosts, banned until {:?}", until);
+                return;
+            }
+            let url = self
+                .hosts
+                .url(idx)
+                .join("/api/v3/ping")
+                .expect("invalid url");
+            let permit = self.rate_limit.acquire_permits(
+                self.weight_of(&PPing::new()),
+                LimitBucket::Api,
+                DEFAULT_UID,
+                None,
+                RequestPriority::Low,
+                self.rate_limit_timeout,
+                false,
+            );
+            if let Err(e) = permit.await {
+                warn!("probe {} skipped: {}", self.hosts.url(idx), e);
+                continue;
+            }
+            let started = Instant::now();
+            match self.conn.get(url).send().await {
+                Ok(resp) => {
+                    let meta = ResponseMeta::from_headers(resp.status().as_u16(), resp.headers());
+                    self.record_response(&meta, DEFAULT_UID).await;
+                    if resp.status().is_success() {
+                        self.hosts.record_success(idx, started.elapsed());
+                    } else {
+                        warn!("probe {} failed: {}", self.hosts.url(idx), resp.status());
+                        self.hosts.record_failure(idx);
+                    }
+                }
+                Err(e) => {
+                    warn!("probe {} failed: {}", self.hosts.url(idx), e.without_url());
+                    self.hosts.record_failure(idx);
+                }
             }
-            Err(e) => Err(e),
         }
     }
 
-    /// 获取交易对信息
-    pub fn get_exchange_info(&self) -> Option<&ExchangeInfo> {
-        self.exchange_info.as_ref().as_ref()
+    /// 在后台每隔interval测量一次所有地址的延迟
+    fn spawn_host_prober(&self, interval: Duration) {
+        self.spawn_background(move |rest_conn| async move {
+            let mut ticker = time::interval_at(time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                rest_conn.probe_hosts().await;
+            }
+        });
     }
 
-    /// 获取交易对的信息，以便能够调整价格、数量
-    pub fn symbol_info(&self, symbol: &str) -> Option<SymbolInfo> {
-        match self.get_exchange_info() {
-            Some(ex_info) => ex_info.symbol_info(symbol).cloned(),
-            None => None,
+    /// 所有REST地址的健康统计(平均延迟、成功和失败次数等)
+    pub fn host_stats(&self) -> Vec<HostStats> {
+        self.hosts.stats()
+    }
+
+    /// 在后台每隔interval同步一次服务器时间
+    fn spawn_clock_syncer(&self, interval: Duration) {
+        self.spawn_background(move |rest_conn| async move {
+            let mut ticker = time::interval_at(time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = rest_conn.sync_clock().await {
+                    warn!("sync clock failed: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 采样服务器时间，估算本地时间与服务器时间之差以及往返时间，
+    /// 之后所有克隆的签名请求都将使用校正后的timestamp
+    pub async fn sync_clock(&self) -> BiAnResult<()> {
+        let mut best: Option<(u128, u64, u128, Duration)> = None;
+        for _ in 0..CLOCK_SYNC_SAMPLES {
+            let started = Instant::now();
+            let before = self.clock.source().now_millis() as u128;
+            let server = self.server_time().await?;
+            let after = self.clock.source().now_millis() as u128;
+            let rtt = started.elapsed();
+            if best.is_none_or(|(.., best_rtt)| rtt < best_rtt) {
+                best = Some((before, server, after, rtt));
+            }
+        }
+        if let Some((before, server, after, rtt)) = best {
+            self.clock.record(before, server, after, rtt);
+            debug!(
+                "clock synced, offset: {}ms, rtt: {:?}",
+                self.clock.offset(),
+                rtt
+            );
         }
+        Ok(())
+    }
+
+    /// 本地时间与服务器时间的同步状态，可与WsClient共享
+    pub fn clock_sync(&self) -> ClockSync {
+        self.clock.clone()
+    }
+
+    /// 校正后的当前时间(毫秒)，从构建时设置的Clock获取
+    pub fn timestamp(&self) -> u128 {
+        self.clock.now()
+    }
+
+    /// 在后台不断尝试加载exchange_info，直到加载成功
+    fn spawn_exchange_info_loader(&self) {
+        self.spawn_background(|rest_conn| async move {
+            let rest_conn = rest_conn.with_priority(RequestPriority::Low);
+            let mut interval = Duration::from_secs(1);
+            while let Err(e) = rest_conn.ensure_exchange_info().await {
+                warn!(
+                    "load exchange_info failed: {}, retry after {:?}",
+                    e, interval
+                );
+                time::sleep(interval).await;
+                interval = (interval * 2).min(Duration::from_secs(60));
+            }
+        });
+    }
+
+    /// 获取当前版本的交易对信息，尚未加载时返回None
+    pub fn get_exchange_info(&self) -> Option<Arc<ExchangeInfo>> {
+        self.exchange_info.get()
+    }
+
+    /// 订阅exchange_info的变化(上架、下架、状态变化、过滤器变化、杠杆权限变化)，
+    /// 每次刷新exchange_info后，新旧版本之间的变化都将发送到返回的Receiver中
+    ///
+    /// ```rust
+    /// let mut events = rest_conn.subscribe_exchange_info_events();
+    /// while let Ok(event) = events.recv().await {
+    ///     if let ExchangeInfoEvent::SymbolAdded { symbol } = event {
+    ///         println!("new listing: {symbol}");
+    ///     }
+    /// }
+    /// ```
+    pub fn subscribe_exchange_info_events(&self) -> broadcast::Receiver<ExchangeInfoEvent> {
+        self.exchange_info.subscribe()
+    }
+
+    /// 订阅限速相关的事件，例如收到418或429后的封禁事件
+    pub fn subscribe_rate_limit_events(&self) -> broadcast::Receiver<RateLimitEvent> {
+        self.rate_limit.subscribe()
+    }
+
+    /// 替换覆盖内置权重的权重表，所有克隆立即生效，币安调整权重时无需升级本crate
+    pub fn set_weight_table(&self, table: WeightTable) {
+        self.weights.store(Arc::new(table));
+    }
+
+    /// 当前覆盖内置权重的权重表
+    pub fn weight_table(&self) -> Arc<WeightTable> {
+        self.weights.load_full()
+    }
+
+    /// 请求实际使用的权重：优先使用`set_weight_table()`设置的权重表，其次是`Endpoint::weight()`
+    pub fn weight_of<E: Endpoint>(&self, endpoint: &E) -> RateLimitParam {
+        self.weights
+            .load()
+            .weight(E::METHOD, E::PATH, endpoint.weight_arg())
+            .unwrap_or_else(|| endpoint.weight())
+    }
+
+    /// 所有限速的当前状态(上限、剩余值、重置时间)，包括每个账户的下单次数
+    pub async fn rate_limit_snapshot(&self) -> Vec<BucketSnapshot> {
+        self.rate_limit.snapshot().await
+    }
+
+    /// 当前是否处于封禁状态，是则返回封禁的结束时间
+    pub fn banned_until(&self) -> Option<SystemTime> {
+        self.rate_limit.banned_until()
+    }
+
+    /// 获取交易对的信息，以便能够调整价格、数量
+    pub fn symbol_info(&self, symbol: &str) -> Option<SymbolInfoRef> {
+        self.exchange_info.symbol_info(symbol)
     }
 
     async fn check_rest_resp(resp: reqwest::Response) -> BiAnResult<reqwest::Response> {
@@ -174,20 +1044,41 @@ impl RestConn {
         }
     }
 
-    /// 生成完整的URL
-    fn make_url<P>(&self, path: &str, params: &P) -> Url
+    /// 生成完整的URL，以及需要放在请求体中的参数
+    ///
+    /// 开启signed_body时，签名的POST/PUT/DELETE请求的参数放在请求体中，此时URL中没有参数，
+    /// 币安对`query + body`计算签名，因此直接对请求体签名即可
+    async fn make_url<P>(
+        &self,
+        base_url: &Url,
+        method: RestMethod,
+        path: &str,
+        params: &P,
+        signer: &dyn Signer,
+    ) -> BiAnResult<(Url, Option<String>)>
     where
         P: Serialize + Param + Debug,
     {
-        let mut url = self.base_url.join(path).expect("invalid url");
-        let payload = params.payload(&self.api_sec_key);
+        let mut url = base_url.join(path).expect("invalid url");
+        let opts = SignOptions {
+            recv_window: self.recv_window,
+            timestamp_unit: self.timestamp_unit,
+            time_offset: self.clock.offset(),
+            clock: Some(self.clock.source().clone()),
+        };
+        let payload = params.payload(signer, &opts).await?;
         let query = serde_urlencoded::to_string(&payload)
             .unwrap_or_else(|x| panic!("encoder to url failed: {x}, {payload:?}"));
-        if !query.is_empty() {
-            url.set_query(Some(&query));
+        if query.is_empty() {
+            return Ok((url, None));
         }
 
-        url
+        let signed = matches!(params.check_type(), CheckType::Trade | CheckType::UserData);
+        if self.signed_body && signed && method != RestMethod::Get {
+            return Ok((url, Some(query)));
+        }
+        url.set_query(Some(&query));
+        Ok((url, None))
     }
 
     /// REST请求，返回响应的Body字符串，否则报错
@@ -222,34 +1113,149 @@ impl RestConn {
     where
         P: Serialize + Param + Debug,
     {
-        let url = self.make_url(path, &params);
+        let method = RestMethod::from_str(method)?;
+        let idempotent = method == RestMethod::Get;
+        let (body, _) = self
+            .request(method, path, params, rate_limit, idempotent)
+            .await
+            .map_err(BiAnApiError::without_meta)?;
+        Ok(body)
+    }
+
+    /// 同`rest_req()`，但同时返回响应的元信息(状态码、已用权重、请求ID、延迟等)，
+    /// 收到了响应但请求失败时，可通过`BiAnApiError::meta()`获取元信息
+    #[tracing::instrument(skip(self))]
+    pub async fn rest_req_with_meta<P>(
+        &self,
+        method: &str,
+        path: &str,
+        params: P,
+        rate_limit: RateLimitParam,
+    ) -> BiAnResult<(RespBody, ResponseMeta)>
+    where
+        P: Serialize + Param + Debug,
+    {
+        let method = RestMethod::from_str(method)?;
+        let idempotent = method == RestMethod::Get;
+        self.request(method, path, params, rate_limit, idempotent)
+            .await
+    }
+
+    /// 发送实现了Endpoint的请求，返回解析后的响应
+    ///```rust
+    ///let params = PKLine::new("BTCUSDT", "1m", None, None, Some(5u16))?;
+    ///let klines: KLines = rest_conn.execute(params).await?;
+    ///```
+    #[tracing::instrument(skip(self))]
+    pub async fn execute<E>(&self, endpoint: E) -> BiAnResult<E::Response>
+    where
+        E: Endpoint,
+    {
+        let (res, _) = self
+            .execute_with_meta(endpoint)
+            .await
+            .map_err(BiAnApiError::without_meta)?;
+        Ok(res)
+    }
+
+    /// 同`execute()`，但同时返回响应的元信息，
+    /// 收到了响应但请求失败(包括响应无法解析)时，可通过`BiAnApiError::meta()`获取元信息
+    ///```rust
+    ///let (order, meta) = rest_conn.execute_with_meta(params).await?;
+    ///println!("uuid: {:?}, weight: {:?}, latency: {:?}", meta.uuid, meta.used_weight_1m, meta.latency);
+    ///```
+    #[tracing::instrument(skip(self))]
+    pub async fn execute_with_meta<E>(&self, endpoint: E) -> BiAnResult<(E::Response, ResponseMeta)>
+    where
+        E: Endpoint,
+    {
+        let rate_limit = self.weight_of(&endpoint);
+        let (res, meta) = self
+            .request(E::METHOD, E::PATH, endpoint, rate_limit, E::IDEMPOTENT)
+            .await?;
+        match serde_json::from_str::<E::Response>(&res) {
+            Ok(res) => Ok((res, meta)),
+            Err(e) => Err(BiAnApiError::from(e).with_meta(meta)),
+        }
+    }
+
+    /// 发送请求，失败时按照重试策略重试，每次重试都重新签名并重新获取限速值，
+    /// 收到了响应但请求失败时，返回的错误附带该响应的元信息
+    async fn request<P>(
+        &self,
+        method: RestMethod,
+        path: &str,
+        params: P,
+        rate_limit: RateLimitParam,
+        idempotent: bool,
+    ) -> BiAnResult<(RespBody, ResponseMeta)>
+    where
+        P: Serialize + Param + Debug,
+    {
         // 该请求是否需要api_key
         let need_api_key = !matches!(params.check_type(), CheckType::None);
+        let key = self.request_key(params.check_type()).await?;
+        // 下单、撤单等请求优先于行情请求获取权重
+        let priority = self.priority.unwrap_or(
+            if method != RestMethod::Get
+                || matches!(
+                    rate_limit,
+                    RateLimitParam::Order(_) | RateLimitParam::Both(_)
+                )
+            {
+                RequestPriority::High
+            } else {
+                RequestPriority::Normal
+            },
+        );
+        // /api/*接口消耗IP权重，/sapi/*接口根据Param::rate_limit()消耗IP权重或UID权重
+        let bucket = LimitBucket::of(path, params.rate_limit());
+        // 签名请求收到-1021(timestamp超出recvWindow)时，只重新同步一次时间
+        let mut resynced = false;
 
-        // 获取限速值, /sapi的接口不做限速处理
-        if path.starts_with("/api") {
-            self.rate_limit.acquire_permits(rate_limit).await;
-        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
 
-        // 尝试重连5次的方案(每隔1秒重试一次)
-        let mut retry = 6;
-        let mut resp = loop {
-            retry -= 1;
-            if retry != 5 {
-                warn!("Connect retry, remain retry times: {}", retry);
+            // 处于封禁状态时，所有请求都暂停，直到封禁结束
+            self.rate_limit.wait_ban(self.wait_on_ban).await?;
+
+            // 获取限速值
+            if let Some(bucket) = bucket {
+                self.rate_limit
+                    .acquire_permits(
+                        rate_limit,
+                        bucket,
+                        key.uid,
+                        self.scope.as_deref(),
+                        priority,
+                        self.rate_limit_timeout,
+                        self.wait_on_ban,
+                    )
+                    .await?;
             }
 
-            let url = url.clone();
-            let mut req = match RestMethod::from_str(method)? {
+            // 每次重试都重新选择地址，失败过的地址将排在其它地址之后
+            let host = self.hosts.select();
+            let base_url = self.hosts.url(host);
+            let (url, body) = self
+                .make_url(&base_url, method, path, &params, key.signer)
+                .await?;
+            let mut req = match method {
                 RestMethod::Get => self.conn.get(url),
                 RestMethod::Post => self.conn.post(url),
                 RestMethod::Put => self.conn.put(url),
                 RestMethod::Delete => self.conn.delete(url),
             };
+            if let Some(body) = body {
+                req = req
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .body(body);
+            }
 
             if need_api_key {
                 let mut header = header::HeaderMap::new();
-                let api_key = self
+                let api_key = key
                     .api_sec_key
                     .api_key()
                     .ok_or_else(|| BiAnApiError::ApiKeyError)?;
@@ -261,76 +1267,375 @@ impl RestConn {
                 req = req.headers(header);
             }
 
-            match req.send().await {
-                Ok(r) => break Ok(r),
-                Err(e) => {
-                    if e.is_connect() || e.is_timeout() {
-                        error!("connect failed<{}>: {}", e.url().unwrap().to_string(), e);
-                        if retry == 0 {
-                            break Err(BiAnApiError::ConnectError(e.to_string()));
+            let started = Instant::now();
+            // reqwest的错误信息中包含URL，URL中可能有签名，因此去掉URL
+            let (err, failure, meta) = match req.send().await.map_err(|e| e.without_url()) {
+                Ok(resp) => {
+                    /*
+                     * {"content-type": "application/json;charset=UTF-8", "content-length": "374",
+                     *  "connection": "keep-alive", "date": "Fri, 25 Aug 2023 10:12:14 GMT",
+                     *  "server": "nginx", "vary": "Accept-Encoding", "x-mbx-uuid": "947f9327-0c5a-443c-dasd-6469921b2e29",
+                     *  "x-mbx-used-weight": "1", "x-mbx-used-weight-1m": "1", "x-mbx-order-count-10s": "1",
+                     *  "x-mbx-order-count-1d": "1", "strict-transport-security": "max-age=31536000; includeSubdomains",
+                     *  "x-frame-options": "SAMEORIGIN", "x-xss-protection": "1; mode=block",
+                     *  "x-content-type-options": "nosniff", "content-security-policy": "default-src 'self'",
+                     *  "x-content-security-policy": "default-src 'self'", "x-webkit-csp": "default-src 'self'",
+                     *  "cache-control": "no-cache, no-store, must-revalidate", "pragma": "no-cache", "expires": "0",
+                     *  "access-control-allow-origin": "*", "access-control-allow-methods": "GET, HEAD, OPTIONS",
+                     *  "x-cache": "Miss from cloudfront", "via": "1.1 sadfasdfasdf.cloudfront.net (CloudFront)",
+                     *  "x-amz-cf-pop": "OSL50-C1", "x-amz-cf-id": "_dwdsdfdas"}
+                     */
+                    let mut meta =
+                        ResponseMeta::from_headers(resp.status().as_u16(), resp.headers());
+                    if meta.status >= 500 {
+                        self.hosts.record_failure(host);
+                    } else {
+                        self.hosts.record_success(host, started.elapsed());
+                    }
+
+                    self.record_response(&meta, key.uid).await;
+
+                    match Self::check_rest_resp(resp).await {
+                        Ok(resp) => {
+                            let body = resp.text().await.map_err(|e| e.without_url())?;
+                            meta.latency = started.elapsed();
+                            return Ok((body, meta));
                         }
-                        // 每次重试，隔一秒
-                        time::sleep(Duration::from_secs(1)).await;
-                        continue;
+                        Err(e) => (e, FailureKind::Status(meta.status), Some(meta)),
                     }
-                    break Err(BiAnApiError::RequestError(e));
                 }
+                Err(e) if e.is_connect() => {
+                    error!("connect failed<{}{}>: {}", base_url, path, e);
+                    self.hosts.record_failure(host);
+                    (
+                        BiAnApiError::ConnectError(e.to_string()),
+                        FailureKind::Connect,
+                        None,
+                    )
+                }
+                Err(e) if e.is_timeout() => {
+                    error!("request timeout<{}{}>: {}", base_url, path, e);
+                    self.hosts.record_failure(host);
+                    (BiAnApiError::RequestError(e), FailureKind::Timeout, None)
+                }
+                Err(e) => return Err(BiAnApiError::RequestError(e)),
             };
-        }?;
-
-        /*
-         * {"content-type": "application/json;charset=UTF-8", "content-length": "374",
-         *  "connection": "keep-alive", "date": "Fri, 25 Aug 2023 10:12:14 GMT",
-         *  "server": "nginx", "vary": "Accept-Encoding", "x-mbx-uuid": "947f9327-0c5a-443c-dasd-6469921b2e29",
-         *  "x-mbx-used-weight": "1", "x-mbx-used-weight-1m": "1", "x-mbx-order-count-10s": "1",
-         *  "x-mbx-order-count-1d": "1", "strict-transport-security": "max-age=31536000; includeSubdomains",
-         *  "x-frame-options": "SAMEORIGIN", "x-xss-protection": "1; mode=block",
-         *  "x-content-type-options": "nosniff", "content-security-policy": "default-src 'self'",
-         *  "x-content-security-policy": "default-src 'self'", "x-webkit-csp": "default-src 'self'",
-         *  "cache-control": "no-cache, no-store, must-revalidate", "pragma": "no-cache", "expires": "0",
-         *  "access-control-allow-origin": "*", "access-control-allow-methods": "GET, HEAD, OPTIONS",
-         *  "x-cache": "Miss from cloudfront", "via": "1.1 sadfasdfasdf.cloudfront.net (CloudFront)",
-         *  "x-amz-cf-pop": "OSL50-C1", "x-amz-cf-id": "_dwdsdfdas"}
-         */
-        let head = resp.headers();
-        // println!("header: {:?}, status: {}", head, resp.status());
-        // println!( "+ weight +{:?} {:?}", head.get("x-mbx-used-weight"), head.get("x-mbx-used-weight-1m") );
-        // println!( "+ weight +{:?} {:?}", head.get("x-mbx-order-count-10s"), head.get("x-mbx-order-count-1d") );
-
-        // 将返回的已用权重值设置到当前的剩余权重中
-        self.set_rate_limit(head).await;
-
-        resp = Self::check_rest_resp(resp).await?;
-        Ok(resp.text().await.unwrap())
-    }
-
-    async fn set_rate_limit(&self, head: &header::HeaderMap) {
-        // "date": "Fri, 25 Aug 2023 10:14:35 GMT"
-        let date = self
-            .extract_weigth_header::<String>("date", head)
-            .unwrap_or_default();
-        let weight = self.extract_weigth_header::<u32>("x-mbx-used-weight-1m", head);
-        let order_sec10 = self.extract_weigth_header::<u32>("x-mbx-order-count-10s", head);
-        let order_day1 = self.extract_weigth_header::<u32>("x-mbx-order-count-1d", head);
 
-        self.rate_limit
-            .set_permits(date, weight, order_sec10, order_day1)
-            .await;
+            if !resynced
+                && matches!(params.check_type(), CheckType::Trade | CheckType::UserData)
+                && matches!(err, BiAnApiError::BadRequest(-1021, _))
+            {
+                resynced = true;
+                warn!("{} failed: {}, resync clock and retry", path, err);
+                // 同步时间本身也会调用request()，需要Box::pin
+                match Box::pin(self.sync_clock()).await {
+                    Ok(()) => continue,
+                    Err(e) => warn!("sync clock failed: {}", e),
+                }
+            }
+
+            let ctx = RetryContext {
+                method,
+                path,
+                idempotent,
+                attempt,
+                failure,
+                retry_after: meta.as_ref().and_then(|x| x.retry_after),
+            };
+            match self.retry_policy.retry_delay(&ctx) {
+                Some(delay) => {
+                    warn!(
+                        "{} failed: {}, retry #{} after {:?}",
+                        path, err, attempt, delay
+                    );
+                    time::sleep(delay).await;
+                }
+                None => {
+                    return Err(match meta {
+                        Some(meta) => err.with_meta(meta),
+                        None => err,
+                    });
+                }
+            }
+        }
     }
 
-    fn extract_weigth_header<T: FromStr>(&self, key: &str, head: &header::HeaderMap) -> Option<T> {
-        if let Some(weight) = head.get(key)
-            && let Ok(used) = weight.to_str()
-        {
-            return used.parse::<T>().ok();
+    /// 将返回的已用权重值设置到当前的剩余权重中，收到418或429时记录封禁状态
+    async fn record_response(&self, meta: &ResponseMeta, uid: &str) {
+        self.set_rate_limit(meta, uid).await;
+        if matches!(meta.status, 418 | 429) {
+            self.rate_limit.ban(meta.status, meta.retry_after).await;
         }
-        None
+    }
+
+    async fn set_rate_limit(&self, meta: &ResponseMeta, uid: &str) {
+        // "date": "Fri, 25 Aug 2023 10:14:35 GMT"
+        let date = meta.date.clone().unwrap_or_default();
+        let used = UsedPermits::from_counters(&meta.rate_limit_counters);
+        self.rate_limit.set_permits(uid, date, used).await;
     }
 }
 
-pub fn timestamp() -> u128 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_millis()
+#[cfg(test)]
+pub(crate) mod tt {
+    use super::*;
+
+    #[test]
+    fn test_response_meta() {
+        let mut head = header::HeaderMap::new();
+        head.insert("date", "Fri, 25 Aug 2023 10:12:14 GMT".parse().unwrap());
+        head.insert("x-mbx-uuid", "947f9327-0c5a-443c".parse().unwrap());
+        head.insert("x-mbx-used-weight-1m", "12".parse().unwrap());
+        head.insert("x-mbx-order-count-10s", "3".parse().unwrap());
+        head.insert("x-sapi-used-ip-weight-1m", "100".parse().unwrap());
+        head.insert("retry-after", "30".parse().unwrap());
+
+        let meta = ResponseMeta::from_headers(429, &head);
+        assert_eq!(meta.status, 429);
+        assert_eq!(meta.used_weight_1m, Some(12));
+        assert_eq!(meta.order_count_10s, Some(3));
+        assert_eq!(meta.order_count_1d, None);
+        assert_eq!(meta.rate_limit_counters.len(), 3);
+        assert_eq!(meta.uuid.as_deref(), Some("947f9327-0c5a-443c"));
+        assert_eq!(meta.retry_after, Some(Duration::from_secs(30)));
+    }
+
+    #[tokio::test]
+    async fn test_background_tasks() {
+        let tasks = Arc::new(BackgroundTasks::default());
+        let handle = tokio::spawn(std::future::pending::<()>());
+        tasks.0.lock().unwrap().push(handle.abort_handle());
+
+        let clone = tasks.clone();
+        drop(tasks);
+        tokio::task::yield_now().await;
+        assert!(!handle.is_finished());
+        // 所有持有者都被drop后，后台任务被中止
+        drop(clone);
+        assert!(handle.await.unwrap_err().is_cancelled());
+    }
+
+    /// 本地的HTTP服务，每个请求都返回response，返回服务地址和已收到的请求数
+    async fn serve(response: &'static str) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let _ = stream.read(&mut buf).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (format!("http://{addr}"), requests)
+    }
+
+    /// 本地的HTTP服务，按请求行的前缀(例如`POST /api/v3/order`)依次返回routes中对应的响应，
+    /// 最后一个响应重复返回，没有对应的前缀时返回404，返回服务地址和已收到的请求
+    pub(crate) async fn serve_routes(
+        routes: Vec<(&'static str, Vec<&'static str>)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let received = requests.clone();
+        tokio::spawn(async move {
+            let mut sent = vec![0; routes.len()];
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let route = routes.iter().position(|x| request.starts_with(x.0));
+                received.lock().unwrap().push(request);
+                let response = match route {
+                    Some(i) => {
+                        let responses = &routes[i].1;
+                        sent[i] += 1;
+                        responses[(sent[i] - 1).min(responses.len() - 1)]
+                    }
+                    None => {
+                        "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                    }
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        (format!("http://{addr}"), requests)
+    }
+
+    #[tokio::test]
+    async fn test_weight_override() {
+        use crate::client::params::{PDepth, PPrice};
+
+        let table = WeightTable::from_json(r#"{ "GET /api/v3/depth": { "weight": 7 } }"#).unwrap();
+        let rest_conn = RestConn::builder(ApiSecKey::default())
+            .load_exchange_info(false)
+            .weight_table(table)
+            .build()
+            .await
+            .unwrap();
+        let w = |x: RateLimitParam| match x {
+            RateLimitParam::Weight(n) => n,
+            _ => unreachable!(),
+        };
+
+        // 覆盖的权重表优先于内置的权重表，表中没有的接口仍使用内置的权重
+        let depth = PDepth::new("BTCUSDT", Some(1000)).unwrap();
+        assert_eq!(w(depth.weight()), 50);
+        assert_eq!(w(rest_conn.weight_of(&depth)), 7);
+        assert_eq!(w(rest_conn.weight_of(&PPrice::new(vec!["BTCUSDT"]))), 2);
+
+        // 运行时替换，所有克隆立即生效
+        let clone = rest_conn.clone();
+        rest_conn.set_weight_table(
+            WeightTable::from_json(
+                r#"{ "GET /api/v3/depth": { "weight": 100, "steps": [[1000, 30]] } }"#,
+            )
+            .unwrap(),
+        );
+        assert_eq!(w(clone.weight_of(&depth)), 30);
+        assert_eq!(
+            w(clone.weight_of(&PDepth::new("BTCUSDT", Some(5000)).unwrap())),
+            100
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_key() {
+        let pool = KeyPool::new()
+            .add("a", ApiSecKey::default())
+            .add("b", ApiSecKey::default());
+        let rest_conn = RestConn::builder(ApiSecKey::default())
+            .key_pool(pool)
+            .load_exchange_info(false)
+            .build()
+            .await
+            .unwrap();
+        rest_conn
+            .rate_limit
+            .acquire_permits(
+                RateLimitParam::Order(1),
+                LimitBucket::Api,
+                "a",
+                None,
+                RequestPriority::High,
+                None,
+                true,
+            )
+            .await
+            .unwrap();
+        let uid = |conn: RestConn, check_type| async move {
+            conn.request_key(check_type)
+                .await
+                .map(|x| x.uid.to_string())
+        };
+
+        // 行情请求选择剩余下单次数最多的key
+        assert_eq!(
+            uid(rest_conn.clone(), CheckType::MarketData).await.unwrap(),
+            "b"
+        );
+        // 签名请求(包括只读的查询)使用自身的key
+        assert_eq!(
+            uid(rest_conn.clone(), CheckType::UserData).await.unwrap(),
+            DEFAULT_UID
+        );
+        assert_eq!(
+            uid(rest_conn.clone(), CheckType::Trade).await.unwrap(),
+            DEFAULT_UID
+        );
+        // with_key()优先
+        assert_eq!(
+            uid(rest_conn.with_key("a").unwrap(), CheckType::UserData)
+                .await
+                .unwrap(),
+            "a"
+        );
+        // 选择的key不存在时返回错误，而不是使用其它的key
+        let unknown = RestConn {
+            key: Some(Arc::from("c")),
+            ..rest_conn.clone()
+        };
+        assert!(matches!(
+            uid(unknown, CheckType::MarketData).await,
+            Err(BiAnApiError::ArgumentError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_error_meta() {
+        let (url, _) = serve(
+            "HTTP/1.1 400 Bad Request\r\nx-mbx-uuid: 947f9327\r\nx-mbx-used-weight-1m: 12\r\ncontent-length: 32\r\nconnection: close\r\n\r\n{\"code\":-1100,\"msg\":\"bad param\"}",
+        )
+        .await;
+        let rest_conn = RestConn::builder(ApiSecKey::default())
+            .base_url(url)
+            .load_exchange_info(false)
+            .build()
+            .await
+            .unwrap();
+
+        // 失败的响应同样返回元信息
+        let err = rest_conn
+            .rest_req_with_meta(
+                "get",
+                "/api/v3/ping",
+                PPing::new(),
+                RateLimitParam::Weight(1),
+            )
+            .await
+            .unwrap_err();
+        let meta = err.meta().unwrap();
+        assert_eq!(meta.status, 400);
+        assert_eq!(meta.uuid.as_deref(), Some("947f9327"));
+        assert_eq!(meta.used_weight_1m, Some(12));
+        assert!(matches!(
+            err.without_meta(),
+            BiAnApiError::BadRequest(-1100, _)
+        ));
+
+        // 不返回元信息的接口返回实际的错误
+        let err = rest_conn
+            .rest_req(
+                "get",
+                "/api/v3/ping",
+                PPing::new(),
+                RateLimitParam::Weight(1),
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BiAnApiError::BadRequest(-1100, _)));
+    }
+
+    #[tokio::test]
+    async fn test_probe_hosts_banned() {
+        use std::sync::atomic::Ordering;
+
+        let (url, requests) = serve(
+            "HTTP/1.1 429 Too Many Requests\r\nretry-after: 60\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        )
+        .await;
+        let rest_conn = RestConn::builder(ApiSecKey::default())
+            .base_url(url)
+            .load_exchange_info(false)
+            .build()
+            .await
+            .unwrap();
+
+        // 探测收到429时同样记录封禁状态
+        rest_conn.probe_hosts().await;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(rest_conn.banned_until().is_some());
+
+        // 封禁期间不再探测
+        rest_conn.probe_hosts().await;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,162 @@
+use super::RestMethod;
+use std::{fmt::Debug, time::Duration};
+
+/// 请求失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// 无法建立连接，请求一定没有到达服务器
+    Connect,
+    /// 超时，请求可能已经被服务器执行
+    Timeout,
+    /// 服务器返回了错误状态码
+    Status(u16),
+}
+
+/// 一次失败的请求，由RetryPolicy决定是否重试
+#[derive(Debug)]
+pub struct RetryContext<'a> {
+    pub method: RestMethod,
+    pub path: &'a str,
+    /// 该请求是否幂等(重复执行不会产生副作用)，默认只有GET请求是幂等的
+    pub idempotent: bool,
+    /// 已经失败的次数，从1开始
+    pub attempt: u32,
+    pub failure: FailureKind,
+    /// 响应头中的Retry-After
+    pub retry_after: Option<Duration>,
+}
+
+/// 重试策略
+///
+/// 默认使用`ExponentialBackoff`，可通过`RestConnBuilder::retry_policy()`设置自定义的策略
+pub trait RetryPolicy: Debug + Send + Sync {
+    /// 返回Some(delay)表示等待delay后重试，返回None表示不再重试
+    fn retry_delay(&self, ctx: &RetryContext<'_>) -> Option<Duration>;
+}
+
+/// 指数退避的重试策略
+///
+/// - 连接失败时总是重试
+/// - 429(请求被限速拒绝，未被执行)时总是重试，并至少等待Retry-After
+/// - 超时和5xx时，只重试幂等的请求，下单等非幂等请求的结果由调用方确认
+/// - 其它错误不重试
+///
+/// 第n次重试前等待`base_delay * 2^(n-1)`(不超过max_delay)，
+/// 开启jitter时，实际等待时间在该值的一半到该值之间随机，避免多个客户端同时重试
+#[derive(Debug, Clone)]
+pub struct ExponentialBackoff {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    pub jitter: bool,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+        }
+    }
+}
+
+impl ExponentialBackoff {
+    pub fn new(max_retries: u32, base_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            base_delay,
+            ..Default::default()
+        }
+    }
+
+    /// 第attempt次失败后的退避时间
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let half = delay / 2;
+        half + half.mul_f64(fastrand::f64())
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn retry_delay(&self, ctx: &RetryContext<'_>) -> Option<Duration> {
+        if ctx.attempt > self.max_retries {
+            return None;
+        }
+
+        let retryable = match ctx.failure {
+            FailureKind::Connect => true,
+            FailureKind::Status(429) => true,
+            FailureKind::Timeout => ctx.idempotent,
+            FailureKind::Status(s) => s >= 500 && ctx.idempotent,
+        };
+        if !retryable {
+            return None;
+        }
+
+        let delay = self.backoff(ctx.attempt);
+        Some(ctx.retry_after.map_or(delay, |x| x.max(delay)))
+    }
+}
+
+/// 从不重试
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoRetry;
+
+impl RetryPolicy for NoRetry {
+    fn retry_delay(&self, _ctx: &RetryContext<'_>) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    fn ctx(method: RestMethod, attempt: u32, failure: FailureKind) -> RetryContext<'static> {
+        RetryContext {
+            method,
+            path: "/api/v3/order",
+            idempotent: method == RestMethod::Get,
+            attempt,
+            failure,
+            retry_after: None,
+        }
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = ExponentialBackoff {
+            jitter: false,
+            ..ExponentialBackoff::new(3, Duration::from_secs(1))
+        };
+
+        let get_503 = ctx(RestMethod::Get, 3, FailureKind::Status(503));
+        assert_eq!(policy.retry_delay(&get_503), Some(Duration::from_secs(4)));
+        let get_503 = ctx(RestMethod::Get, 4, FailureKind::Status(503));
+        assert_eq!(policy.retry_delay(&get_503), None);
+
+        // 非幂等请求超时或5xx时不重试，连接失败时重试
+        let post_503 = ctx(RestMethod::Post, 1, FailureKind::Status(503));
+        assert_eq!(policy.retry_delay(&post_503), None);
+        let post_timeout = ctx(RestMethod::Post, 1, FailureKind::Timeout);
+        assert_eq!(policy.retry_delay(&post_timeout), None);
+        let post_connect = ctx(RestMethod::Post, 1, FailureKind::Connect);
+        assert_eq!(
+            policy.retry_delay(&post_connect),
+            Some(Duration::from_secs(1))
+        );
+
+        let mut post_429 = ctx(RestMethod::Post, 1, FailureKind::Status(429));
+        post_429.retry_after = Some(Duration::from_secs(10));
+        assert_eq!(policy.retry_delay(&post_429), Some(Duration::from_secs(10)));
+
+        let get_400 = ctx(RestMethod::Get, 1, FailureKind::Status(400));
+        assert_eq!(policy.retry_delay(&get_400), None);
+    }
+}
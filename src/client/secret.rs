@@ -0,0 +1,76 @@
+use std::fmt::{Debug, Display};
+use zeroize::Zeroize;
+
+/// 替换敏感信息(apiKey、signature、listenKey等)的字符串
+pub(crate) const REDACTED: &str = "***";
+
+/// 敏感信息(私钥、api_key等)的包装，Debug和Display时不输出内容，drop时清零内存
+///
+/// ```rust
+/// let sec_key = Secret::new("MOIeI2mK13IEyrLHfCwh");
+/// assert_eq!(format!("{sec_key:?}"), "Secret(***)");
+/// assert_eq!(sec_key.expose(), "MOIeI2mK13IEyrLHfCwh");
+/// ```
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+
+    /// 获取原始内容，只应在签名等必须使用原始内容的地方调用
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for Secret {
+    fn from(secret: String) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(secret: &str) -> Self {
+        Self(secret.to_string())
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Secret({REDACTED})")
+    }
+}
+
+impl Display for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Debug时将Some中的内容替换为`***`
+pub(crate) fn redact<T>(x: &Option<T>) -> Option<&'static str> {
+    x.as_ref().map(|_| REDACTED)
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    #[test]
+    fn test_secret() {
+        let secret = Secret::new("abcdefg");
+        assert_eq!(format!("{secret:?}"), "Secret(***)");
+        assert_eq!(format!("{secret}"), "***");
+        assert_eq!(secret.expose(), "abcdefg");
+        assert_eq!(redact(&Some("abc")), Some("***"));
+        assert_eq!(redact::<String>(&None), None);
+    }
+}
@@ -0,0 +1,196 @@
+//! 同一台机器上多个进程共享的限速预算
+//!
+//! IP的权重和请求次数由该IP上的所有进程共同消耗，各进程将已使用的值记录在同一个内存映射文件中，
+//! 文件由若干个槽组成，每个槽对应一个限速(类型 + 时间段)，槽的状态为一个u64：
+//! 高32位为时间段的序号(epoch毫秒 / 时间段长度)，低32位为该时间段内已使用的值，
+//! 进入新的时间段时，第一个修改该槽的进程将已使用的值清零
+//!
+//! 扣除时通过`SharedSlot::try_add`在同一个CAS中检查上限，多个进程同时请求也不会超出限速
+
+use crate::errors::BiAnResult;
+use memmap2::MmapMut;
+use std::{
+    fs::OpenOptions,
+    path::Path,
+    ptr::NonNull,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// 文件中槽的个数
+const SLOTS: usize = 32;
+/// 每个槽占用的字节数：key(u64) + state(u64)
+const SLOT_SIZE: usize = 16;
+
+/// 共享的限速类型，只有按IP计数的限速才会共享
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SharedKind {
+    Weight = 1,
+    RawRequests = 2,
+    SapiIp = 3,
+}
+
+/// 内存映射的共享文件，所有克隆共享同一个映射
+#[derive(Clone)]
+pub(crate) struct SharedBudget {
+    /// 只用于保持映射有效，不通过它读写
+    _mmap: Arc<MmapMut>,
+    /// 映射的可写起始地址，在open时获取
+    ptr: NonNull<u8>,
+}
+
+// Safety: ptr指向_mmap持有的映射，映射在所有克隆drop前不会被释放，
+// 且映射的内容只通过AtomicU64读写，多个线程同时访问不会产生数据竞争
+unsafe impl Send for SharedBudget {}
+unsafe impl Sync for SharedBudget {}
+
+impl SharedBudget {
+    /// 打开共享文件，不存在时创建
+    pub(crate) fn open(path: impl AsRef<Path>) -> BiAnResult<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        let size = (SLOTS * SLOT_SIZE) as u64;
+        if file.metadata()?.len() < size {
+            file.set_len(size)?;
+        }
+        // Safety: 文件只通过原子操作读写，其它进程同时修改不会导致未定义行为
+        let mut mmap = unsafe { MmapMut::map_mut(&file)? };
+        let ptr = NonNull::new(mmap.as_mut_ptr()).expect("mmap returned a null pointer");
+        Ok(Self {
+            _mmap: Arc::new(mmap),
+            ptr,
+        })
+    }
+
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        // Safety: 映射的起始地址按页对齐，offset为8的倍数且在映射范围内，
+        // ptr来自as_mut_ptr，通过AtomicU64写入是允许的
+        unsafe { self.ptr.add(offset).cast::<AtomicU64>().as_ref() }
+    }
+
+    /// 获取限速对应的槽，不存在时占用一个空槽，槽已用完时返回None
+    pub(crate) fn slot(&self, kind: SharedKind, interval_ms: i64) -> Option<SharedSlot> {
+        let key = (kind as u64) << 48 | interval_ms as u64;
+        for idx in 0..SLOTS {
+            let slot_key = self.atomic(idx * SLOT_SIZE);
+            match slot_key.compare_exchange(0, key, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => {}
+                Err(x) if x == key => {}
+                Err(_) => continue,
+            }
+            return Some(SharedSlot {
+                budget: self.clone(),
+                offset: idx * SLOT_SIZE + 8,
+                interval_ms,
+            });
+        }
+        None
+    }
+}
+
+/// 共享文件中的一个槽
+#[derive(Clone)]
+pub(crate) struct SharedSlot {
+    budget: SharedBudget,
+    offset: usize,
+    interval_ms: i64,
+}
+
+impl SharedSlot {
+    fn window(&self, now_ms: i64) -> u64 {
+        (now_ms / self.interval_ms) as u32 as u64
+    }
+
+    /// 当前时间段内所有进程已使用的值
+    pub(crate) fn used(&self, now_ms: i64) -> u32 {
+        let state = self.budget.atomic(self.offset).load(Ordering::Acquire);
+        if state >> 32 >= self.window(now_ms) {
+            state as u32
+        } else {
+            0
+        }
+    }
+
+    /// 修改当前时间段内已使用的值，返回修改后的值，f返回None时不修改，返回当前值
+    fn update(&self, now_ms: i64, f: impl Fn(u32) -> Option<u32>) -> Result<u32, u32> {
+        let state = self.budget.atomic(self.offset);
+        let window = self.window(now_ms);
+        let mut cur = state.load(Ordering::Acquire);
+        loop {
+            // 其它进程的时钟可能稍快，已经进入了下一个时间段
+            let (window, used) = if cur >> 32 >= window {
+                (cur >> 32, cur as u32)
+            } else {
+                (window, 0)
+            };
+            let Some(used) = f(used) else {
+                return Err(used);
+            };
+            match state.compare_exchange_weak(
+                cur,
+                window << 32 | used as u64,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return Ok(used),
+                Err(x) => cur = x,
+            }
+        }
+    }
+
+    /// 已使用的值加上n后不超过limit时才增加，成功时返回Ok(增加后的值)，否则返回Err(当前值)
+    pub(crate) fn try_add(&self, n: u32, limit: u32, now_ms: i64) -> Result<u32, u32> {
+        self.update(now_ms, |x| x.checked_add(n).filter(|x| *x <= limit))
+    }
+
+    pub(crate) fn sub(&self, n: u32, now_ms: i64) -> u32 {
+        self.update(now_ms, |x| Some(x.saturating_sub(n)))
+            .unwrap_or_else(|x| x)
+    }
+
+    /// 响应头中的已使用值是该IP上所有进程的总和，取较大者
+    pub(crate) fn set_max(&self, n: u32, now_ms: i64) -> u32 {
+        self.update(now_ms, |x| Some(x.max(n)))
+            .unwrap_or_else(|x| x)
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    #[test]
+    fn test_shared_slot() {
+        let path = std::env::temp_dir().join(format!("ba_api_limit_{}", uuid::Uuid::new_v4()));
+        // 模拟两个进程
+        let a = SharedBudget::open(&path).unwrap();
+        let b = SharedBudget::open(&path).unwrap();
+
+        let now_ms = 1_700_000_000_000;
+        let weight_a = a.slot(SharedKind::Weight, 60_000).unwrap();
+        let weight_b = b.slot(SharedKind::Weight, 60_000).unwrap();
+        let raw_b = b.slot(SharedKind::RawRequests, 300_000).unwrap();
+        assert_eq!(weight_a.try_add(10, 100, now_ms), Ok(10));
+        assert_eq!(weight_b.try_add(5, 100, now_ms), Ok(15));
+        assert_eq!(weight_a.used(now_ms), 15);
+        assert_eq!(raw_b.used(now_ms), 0);
+
+        assert_eq!(weight_b.set_max(12, now_ms), 15);
+        // 超出上限时不增加
+        assert_eq!(weight_a.try_add(6, 20, now_ms), Err(15));
+        assert_eq!(weight_b.try_add(5, 20, now_ms), Ok(20));
+        assert_eq!(weight_a.sub(5, now_ms), 15);
+        assert_eq!(weight_a.sub(5, now_ms), 10);
+        // 进入新的时间段后清零
+        assert_eq!(weight_b.used(now_ms + 60_000), 0);
+        assert_eq!(weight_a.try_add(1, 100, now_ms + 60_000), Ok(1));
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -3,11 +3,11 @@ use super::{
         PAccount, PAllOrders, PCancelOpenOrders, PCancelOrder, PGetOpenOrders, PGetOrder,
         PMyTrades, POrder, PRateLimitInfo,
     },
-    rate_limit::RateLimitParam,
+    endpoint::Endpoint,
     RestConn,
 };
 use crate::{
-    errors::BiAnResult,
+    errors::{BiAnApiError, BiAnResult},
     types::{
         account::Account,
         order::{CancelOpenOrdersInfo, CancelOrderInfo, MyTrades, Order, OrderInfo},
@@ -15,21 +15,38 @@ use crate::{
     utils::SymbolInfoExt,
 };
 use ba_types::RateLimit;
-use tracing::instrument;
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{instrument, warn};
+
+/// 下单结果未知时，查询订单的次数
+const RECONCILE_TIMES: u32 = 3;
+/// 下单结果未知时，每次查询订单之前等待的时间
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 将查询订单的响应转换为下单的响应，查询订单的响应中没有transactTime，使用updateTime代替
+fn order_from_query(res: &str) -> BiAnResult<Order> {
+    let mut order = serde_json::from_str::<Value>(res)?;
+    if let Some(obj) = order.as_object_mut()
+        && !obj.contains_key("transactTime")
+    {
+        let transact_time = obj
+            .get("updateTime")
+            .or_else(|| obj.get("time"))
+            .cloned()
+            .unwrap_or(Value::from(0));
+        obj.insert("transactTime".to_string(), transact_time);
+    }
+    Ok(serde_json::from_value::<Order>(order)?)
+}
 
 /// 现货账户和现货交易接口
 impl RestConn {
     /// 获取现货账户信息
     #[instrument(skip(self))]
     pub async fn account(&self) -> BiAnResult<Account> {
-        let path = "/api/v3/account";
         let params = PAccount::new(Some(true));
-        let rate_limit = 20;
-        let res = self
-            .rest_req("get", path, params, RateLimitParam::Weight(rate_limit))
-            .await?;
-        let account_info = serde_json::from_str::<Account>(&res)?;
-        Ok(account_info)
+        self.execute(params).await
     }
 
     /// 现货下单接口  
@@ -84,6 +101,12 @@ impl RestConn {
     ) -> BiAnResult<Order> {
         let (mut price, mut qty, mut stop_price, mut iceberg_qty) =
             (price, qty, stop_price, iceberg_qty);
+        // 惰性加载模式下，exchange_info可能还未加载，加载失败时不调整价格和数量
+        if self.get_exchange_info().is_none()
+            && let Err(e) = self.ensure_exchange_info().await
+        {
+            warn!("load exchange_info failed, skip adjusting price and qty: {}", e);
+        }
         if let Some(info) = self.symbol_info(symbol) {
             price = price.map(|x| info.adjust_price(x));
             stop_price = stop_price.map(|x| info.adjust_price(x));
@@ -91,7 +114,6 @@ impl RestConn {
             iceberg_qty = iceberg_qty.map(|x| info.adjust_amount(x));
         }
 
-        let path = "/api/v3/order";
         let params = POrder::new(
             symbol,
             side,
@@ -106,11 +128,40 @@ impl RestConn {
             new_order_resp_type,
         )?;
 
-        let res = self
-            .rest_req("post", path, params, RateLimitParam::Order(1))
-            .await?;
-        let order_info = serde_json::from_str::<Order>(&res)?;
-        Ok(order_info)
+        // 超时或5xx时不会重试下单，而是根据clientOrderId查询订单是否已经下单成功
+        let cid = params.client_order_id().to_string();
+        match self.execute(params).await {
+            Err(e) if e.is_unknown_status() => self.reconcile_order(symbol, &cid, e).await,
+            res => res,
+        }
+    }
+
+    /// 下单结果未知时，根据clientOrderId查询订单，查询到时返回该订单，
+    /// 多次查询都不存在时，返回下单时的错误
+    async fn reconcile_order(
+        &self,
+        symbol: &str,
+        cid: &str,
+        err: BiAnApiError,
+    ) -> BiAnResult<Order> {
+        warn!("order({}) status unknown: {}, query it by clientOrderId", cid, err);
+        for _ in 0..RECONCILE_TIMES {
+            self.clock_sync().source().sleep(RECONCILE_INTERVAL).await;
+
+            // 签名请求总是使用with_key()选择的key或RestConn自身的key，与下单时的账户相同
+            let params = PGetOrder::new(symbol, None, Some(cid))?;
+            let rate_limit = self.weight_of(&params);
+            match self
+                .rest_req("get", PGetOrder::PATH, params, rate_limit)
+                .await
+            {
+                Ok(res) => return order_from_query(&res),
+                // -2013: 订单不存在，可能尚未被撮合引擎处理，稍后再查
+                Err(BiAnApiError::BadRequest(-2013, _)) => continue,
+                Err(e) => warn!("query order({}) failed: {}", cid, e),
+            }
+        }
+        Err(err)
     }
 
     /// (GTC)限价单接口
@@ -259,25 +310,15 @@ impl RestConn {
         orig_cid: Option<&str>,
         new_cid: Option<&str>,
     ) -> BiAnResult<CancelOrderInfo> {
-        let path = "/api/v3/order";
         let params = PCancelOrder::new(symbol, order_id, orig_cid, new_cid)?;
-        let res = self
-            .rest_req("delete", path, params, RateLimitParam::Weight(1))
-            .await?;
-        let cancel = serde_json::from_str::<CancelOrderInfo>(&res)?;
-        Ok(cancel)
+        self.execute(params).await
     }
 
     /// 撤单(撤销某个交易对下的所有挂单，包括OCO挂单)
     #[instrument(skip(self))]
     pub async fn cancel_open_orders(&self, symbol: &str) -> BiAnResult<Vec<CancelOpenOrdersInfo>> {
-        let path = "/api/v3/openOrders";
         let params = PCancelOpenOrders::new(symbol);
-        let res = self
-            .rest_req("delete", path, params, RateLimitParam::Weight(1))
-            .await?;
-        let cancel = serde_json::from_str::<Vec<CancelOpenOrdersInfo>>(&res)?;
-        Ok(cancel)
+        self.execute(params).await
     }
 
     /// 查询订单信息
@@ -288,30 +329,15 @@ impl RestConn {
         order_id: Option<u64>,
         orig_client_order_id: Option<&str>,
     ) -> BiAnResult<OrderInfo> {
-        let path = "/api/v3/order";
         let params = PGetOrder::new(symbol, order_id, orig_client_order_id)?;
-        let rate_limit = 4;
-        let res = self
-            .rest_req("get", path, params, RateLimitParam::Weight(rate_limit))
-            .await?;
-        let order_info = serde_json::from_str::<OrderInfo>(&res)?;
-        Ok(order_info)
+        self.execute(params).await
     }
 
     /// 查询某交易对或所有交易对下的所有当前挂单信息
     #[instrument(skip(self))]
     pub async fn get_open_orders(&self, symbol: Option<&str>) -> BiAnResult<Vec<OrderInfo>> {
-        let path = "/api/v3/openOrders";
-        let (sym, rate_limit) = match symbol {
-            Some(s) => (Some(String::from(s)), 6),
-            None => (None, 80),
-        };
-        let params = PGetOpenOrders::new(sym);
-        let res = self
-            .rest_req("get", path, params, RateLimitParam::Weight(rate_limit))
-            .await?;
-        let open_orders_info = serde_json::from_str::<Vec<OrderInfo>>(&res)?;
-        Ok(open_orders_info)
+        let params = PGetOpenOrders::new(symbol.map(String::from));
+        self.execute(params).await
     }
 
     /// 查询某交易对的所有当前订单信息(包括历史订单)
@@ -324,14 +350,8 @@ impl RestConn {
         end_time: Option<u64>,
         limit: Option<u16>,
     ) -> BiAnResult<Vec<OrderInfo>> {
-        let path = "/api/v3/allOrders";
         let params = PAllOrders::new(symbol, order_id, start_time, end_time, limit);
-        let rate_limit = 20;
-        let res = self
-            .rest_req("get", path, params, RateLimitParam::Weight(rate_limit))
-            .await?;
-        let all_orders_info = serde_json::from_str::<Vec<OrderInfo>>(&res)?;
-        Ok(all_orders_info)
+        self.execute(params).await
     }
 
     /// 获取账户指定交易对的成交历史
@@ -345,29 +365,122 @@ impl RestConn {
         from_id: Option<u64>,
         limit: Option<u16>,
     ) -> BiAnResult<Vec<MyTrades>> {
-        let path = "/api/v3/myTrades";
         let params = PMyTrades::new(symbol, order_id, start_time, end_time, from_id, limit);
-        let mut rate_limit = 20;
-        if order_id.is_some() {
-            rate_limit = 5;
-        }
-        let res = self
-            .rest_req("get", path, params, RateLimitParam::Weight(rate_limit))
-            .await?;
-        let trades_info = serde_json::from_str::<Vec<MyTrades>>(&res)?;
-        Ok(trades_info)
+        self.execute(params).await
     }
 
     /// 查询目前下单数
     #[instrument(skip(self))]
     pub async fn rate_limit_info(&self) -> BiAnResult<Vec<RateLimit>> {
-        let path = "/api/v3/rateLimit/order";
-        let params = PRateLimitInfo::new();
-        let rate_limit = 40;
-        let res = self
-            .rest_req("get", path, params, RateLimitParam::Weight(rate_limit))
-            .await?;
-        let rate_limit_info = serde_json::from_str::<Vec<RateLimit>>(&res)?;
-        Ok(rate_limit_info)
+        self.execute(PRateLimitInfo::new()).await
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+    use crate::{
+        ApiSecKey,
+        client::{
+            clock::ManualClock, exchange_info::ExchangeInfoCachePolicy, key_pool::KeyPool,
+            rest::tt::serve_routes,
+        },
+    };
+    use std::sync::{Arc, Mutex};
+
+    const ORDER_5XX: &str =
+        "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+    const ORDER_NOT_FOUND: &str = "HTTP/1.1 400 Bad Request\r\ncontent-length: 44\r\nconnection: close\r\n\r\n{\"code\":-2013,\"msg\":\"Order does not exist.\"}";
+    const ORDER_FOUND: &str = "HTTP/1.1 200 OK\r\ncontent-length: 411\r\nconnection: close\r\n\r\n{\"symbol\":\"LTCBTC\",\"orderId\":1,\"orderListId\":-1,\"clientOrderId\":\"myOrder1\",\"price\":\"0.1\",\"origQty\":\"1.0\",\"executedQty\":\"0.0\",\"cummulativeQuoteQty\":\"0.0\",\"status\":\"NEW\",\"timeInForce\":\"GTC\",\"type\":\"LIMIT\",\"side\":\"SELL\",\"stopPrice\":\"0.0\",\"icebergQty\":\"0.0\",\"time\":1499827319559,\"updateTime\":1499827319559,\"isWorking\":true,\"workingTime\":1499827319559,\"origQuoteOrderQty\":\"0.000000\",\"selfTradePreventionMode\":\"NONE\"}";
+
+    /// 使用KeyPool中的sub账户下单，返回RestConn、时钟和已收到的请求
+    async fn order_conn(
+        query: Vec<&'static str>,
+    ) -> (RestConn, ManualClock, Arc<Mutex<Vec<String>>>) {
+        let (url, requests) = serve_routes(vec![
+            ("POST /api/v3/order", vec![ORDER_5XX]),
+            ("GET /api/v3/order", query),
+        ])
+        .await;
+        let clock = ManualClock::new(1_700_000_000_000);
+        let pool = KeyPool::new().add_sec_key("sub", "sub_api_key", "sub_sec_key");
+        let rest_conn = RestConn::builder(ApiSecKey::new(Some("main_api_key".into()), None))
+            .sec_key("main_sec_key")
+            .base_url(url)
+            .key_pool(pool)
+            .clock(clock.clone())
+            .load_exchange_info(false)
+            .exchange_info_cache(ExchangeInfoCachePolicy::disabled())
+            .build()
+            .await
+            .unwrap()
+            .with_key("sub")
+            .unwrap();
+        (rest_conn, clock, requests)
+    }
+
+    /// 推进时钟直到下单完成
+    async fn limit_order(rest_conn: RestConn, clock: ManualClock) -> BiAnResult<Order> {
+        let order = tokio::spawn(async move {
+            rest_conn
+                .limit_order("LTCBTC", "sell", 1.0, 0.1, Some("myOrder1"))
+                .await
+        });
+        while !order.is_finished() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            clock.advance(RECONCILE_INTERVAL);
+        }
+        order.await.unwrap()
+    }
+
+    /// 下单和查询订单的请求
+    fn order_requests(requests: &Mutex<Vec<String>>) -> Vec<String> {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| x.contains("/api/v3/order"))
+            .map(|x| x.to_lowercase())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_order_found() {
+        let (rest_conn, clock, requests) = order_conn(vec![ORDER_NOT_FOUND, ORDER_FOUND]).await;
+
+        // 下单返回5xx后，根据clientOrderId查询到了订单
+        let order = limit_order(rest_conn, clock).await.unwrap();
+        assert_eq!(order.order_id(), 1);
+
+        // 查询订单与下单使用同一个账户的key
+        let requests = order_requests(&requests);
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].starts_with("post "));
+        assert!(
+            requests
+                .iter()
+                .all(|x| x.contains("x-mbx-apikey: sub_api_key"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_order_not_found() {
+        let (rest_conn, clock, requests) = order_conn(vec![ORDER_NOT_FOUND]).await;
+
+        // 多次查询都不存在时，返回下单时的错误
+        let err = limit_order(rest_conn, clock).await.unwrap_err();
+        assert!(matches!(err, BiAnApiError::ServerError(_)));
+        assert_eq!(
+            order_requests(&requests).len(),
+            1 + RECONCILE_TIMES as usize
+        );
+    }
+
+    #[test]
+    fn test_order_from_query() {
+        let body = ORDER_FOUND.split("\r\n\r\n").nth(1).unwrap();
+        let order = order_from_query(body).unwrap();
+        assert_eq!(order.order_id(), 1);
+        assert!(order_from_query("{\"code\":-2013}").is_err());
     }
 }
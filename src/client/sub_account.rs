@@ -1,11 +1,10 @@
 use super::{
     params::{PAccountInfo, PSubAccountAssets, PSubAccountList, PSubAccountUniversalTransfer},
-    rate_limit::RateLimitParam,
     RestConn,
 };
 use crate::{
     errors::BiAnResult,
-    types::sub_account::{SubAccountBalances, SubAccounts, UniversalTransfer},
+    types::sub_account::{SubAccounts, UniversalTransfer},
     Balances,
 };
 use ba_types::types::sub_account::AccountInfo;
@@ -20,24 +19,15 @@ impl RestConn {
         email: Option<&str>,
         is_freeze: Option<&str>,
     ) -> BiAnResult<SubAccounts> {
-        let path = "/sapi/v1/sub-account/list";
         let params = PSubAccountList::new(email, is_freeze);
-        let res = self
-            .rest_req("get", path, params, RateLimitParam::Weight(1))
-            .await?;
-        let sub_account_list = serde_json::from_str::<SubAccounts>(&res)?;
-        Ok(sub_account_list)
+        self.execute(params).await
     }
 
     /// 查询子账户资产
     #[instrument(skip(self))]
     pub async fn sub_account_assests(&self, email: &str) -> BiAnResult<Balances> {
-        let path = "/sapi/v3/sub-account/assets";
         let params = PSubAccountAssets::new(email);
-        let res = self
-            .rest_req("get", path, params, RateLimitParam::Weight(1))
-            .await?;
-        let sub_account_balances = serde_json::from_str::<SubAccountBalances>(&res)?;
+        let sub_account_balances = self.execute(params).await?;
         Ok(sub_account_balances.balances)
     }
 
@@ -64,7 +54,6 @@ impl RestConn {
         amount: f64,
         symbol: Option<&str>,
     ) -> BiAnResult<UniversalTransfer> {
-        let path = "/sapi/v1/sub-account/universalTransfer";
         let params = PSubAccountUniversalTransfer::new(
             from_email,
             to_email,
@@ -74,11 +63,7 @@ impl RestConn {
             amount,
             symbol,
         )?;
-        let res = self
-            .rest_req("post", path, params, RateLimitParam::Weight(1))
-            .await?;
-        let res = serde_json::from_str::<UniversalTransfer>(&res)?;
-        Ok(res)
+        self.execute(params).await
     }
 
     /// 子母账户现货资产划转：母账户 <=> 子账户 <=> 子账户
@@ -101,11 +86,6 @@ impl RestConn {
     /// 获取账户信息(VIP等级、是否开启杠杆帐户 及 是否开启合约帐户)
     #[instrument(skip(self))]
     pub async fn account_info(&self) -> BiAnResult<AccountInfo> {
-        let path = "/sapi/v1/account/info";
-        let res = self
-            .rest_req("get", path, PAccountInfo::new(), RateLimitParam::Weight(1))
-            .await?;
-        let account_info = serde_json::from_str::<AccountInfo>(&res)?;
-        Ok(account_info)
+        self.execute(PAccountInfo::new()).await
     }
 }
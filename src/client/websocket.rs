@@ -1,4 +1,13 @@
-use super::params::{PKLine, PSessionLogon, PSessionStatus, PUserDataStream, PWebSocketApi, Param};
+use super::{
+    clock_sync::ClockSync,
+    rest::RestConn,
+    secret::Secret,
+    signer::{Signer, sec_key_signer},
+    params::{
+        PKLine, PSessionLogon, PSessionStatus, PUserDataStream, PWebSocketApi, Param,
+        SignOptions,
+    },
+};
 use crate::{
     ApiSecKey, KLineInterval, WebsocketApiResponse, WsResponse,
     errors::{BiAnApiError, BiAnResult},
@@ -257,11 +266,19 @@ pub struct WsClient {
     // pub ws: Arc<DashMap<(), WS>>,
     ws: Arc<WS>,
     api_sec_key: ApiSecKey,
+    /// 签名方式，默认使用api_sec_key签名
+    signer: Arc<dyn Signer>,
     close_sender: mpsc::Sender<bool>,
     /// 是否登录了
     logon_flag: Arc<AtomicBool>,
     /// 是否订阅了 user data stream
     uds_subscribed: Arc<AtomicBool>,
+    /// 本地时间与服务器时间的同步状态，签名请求的timestamp将根据其校正
+    clock: ClockSync,
+    /// 通过`ws_api_with()`创建时的RestConn，收到-1021时通过它重新同步时钟
+    rest_conn: Option<RestConn>,
+    /// 是否正在重新同步时钟，避免多个-1021响应重复同步
+    resyncing: Arc<AtomicBool>,
 }
 
 impl WsClient {
@@ -293,16 +310,31 @@ impl WsClient {
     pub async fn new(
         channel_path: ChannelPath,
         data_sender: mpsc::Sender<WsResponse>,
+    ) -> BiAnResult<(Self, JoinHandle<()>)> {
+        Self::connect(channel_path, data_sender, |_| {}).await
+    }
+
+    /// 建立连接，init在启动后台任务之前执行，
+    /// 使得后台任务(重新登录、重新同步时钟)使用的是设置好的key、签名方式和时钟
+    async fn connect(
+        channel_path: ChannelPath,
+        data_sender: mpsc::Sender<WsResponse>,
+        init: impl FnOnce(&mut Self),
     ) -> BiAnResult<(Self, JoinHandle<()>)> {
         let ws = WS::new(channel_path).await?;
         let (close_sender, close_receiver) = mpsc::channel::<bool>(1);
-        let s = Self {
+        let mut s = Self {
             ws: Arc::new(ws),
             close_sender,
             api_sec_key: ApiSecKey::default(),
+            signer: Arc::new(ApiSecKey::default()),
             logon_flag: Arc::new(AtomicBool::new(false)),
             uds_subscribed: Arc::new(AtomicBool::new(false)),
+            clock: ClockSync::new(),
+            rest_conn: None,
+            resyncing: Arc::new(AtomicBool::new(false)),
         };
+        init(&mut s);
 
         let task = {
             let mut join_set = JoinSet::new();
@@ -416,6 +448,9 @@ impl WsClient {
         match msg {
             Message::Text(data) => {
                 // warn!("websocket recv: {}", data.as_str());
+                if is_timestamp_error(data.as_str()) {
+                    self.resync_clock();
+                }
                 match serde_json::from_slice::<WsResponse>(data.as_bytes()) {
                     Ok(resp) => {
                         // 如果是登录、订阅账户更新的消息，则保存连接当前是否已经登录、是否已经订阅的状态
@@ -456,6 +491,28 @@ impl WsClient {
         None
     }
 
+    /// 收到-1021(timestamp超出recvWindow)时，通过RestConn重新同步一次时钟，
+    /// 同步进行中再次收到-1021时不重复同步
+    fn resync_clock(&self) {
+        let Some(rest_conn) = self.rest_conn.clone() else {
+            warn!(
+                "websocket api timestamp outside recvWindow, use `ws_api_with()` to resync clock"
+            );
+            return;
+        };
+        if self.resyncing.swap(true, atomic::Ordering::AcqRel) {
+            return;
+        }
+        let resyncing = self.resyncing.clone();
+        tokio::spawn(async move {
+            warn!("websocket api timestamp outside recvWindow, resync clock");
+            if let Err(e) = rest_conn.sync_clock().await {
+                error!("resync clock failed: {e}");
+            }
+            resyncing.store(false, atomic::Ordering::Release);
+        });
+    }
+
     /// 读取关闭WsClient的信号
     async fn read_close_channel(&self, mut close_receiver: mpsc::Receiver<bool>) {
         let dur = tokio::time::Duration::from_millis(100);
@@ -684,9 +741,51 @@ impl WsClient {
         api_sec_key: ApiSecKey,
         data_sender: mpsc::Sender<WsResponse>,
     ) -> BiAnResult<(Self, JoinHandle<()>)> {
-        let (mut s, j) = Self::new(ChannelPath::Api, data_sender).await?;
-        s.api_sec_key = api_sec_key;
-        Ok((s, j))
+        Self::connect(ChannelPath::Api, data_sender, |s| {
+            s.signer = Arc::new(api_sec_key.clone());
+            s.api_sec_key = api_sec_key;
+        })
+        .await
+    }
+
+    /// 使用RestConn的key(包括`with_key()`选择的key)和签名方式建立websocket api连接，
+    /// 并与RestConn共享时钟同步状态，收到-1021响应时通过RestConn重新同步时钟
+    /// ```rust
+    /// let (ws, task) = WsClient::ws_api_with(&rest_conn, data_sender).await?;
+    /// ```
+    pub async fn ws_api_with(
+        rest_conn: &RestConn,
+        data_sender: mpsc::Sender<WsResponse>,
+    ) -> BiAnResult<(Self, JoinHandle<()>)> {
+        let (api_sec_key, signer) = rest_conn.signing_key()?;
+        Self::connect(ChannelPath::Api, data_sender, |s| {
+            s.api_sec_key = api_sec_key;
+            s.signer = signer;
+            s.clock = rest_conn.clock_sync();
+            s.rest_conn = Some(rest_conn.clone());
+        })
+        .await
+    }
+
+    /// 设置签名方式，默认使用`ws_api()`传入的ApiSecKey签名，
+    /// 使用其它Signer时，ApiSecKey只需提供api_key
+    pub fn set_signer(&mut self, signer: impl Signer + 'static) {
+        self.signer = Arc::new(signer);
+    }
+
+    /// 设置签名使用的secret key，保存在`Secret`中，drop时清零内存，
+    /// 此时`ws_api()`传入的ApiSecKey只需提供api_key
+    pub fn set_sec_key(&mut self, sec_key: impl Into<Secret>) {
+        self.signer = sec_key_signer(sec_key);
+    }
+
+    /// 设置签名请求使用的时钟，通常与RestConn共享同一个时钟，
+    /// 通过`ws_api_with()`创建时已自动共享
+    /// ```rust
+    /// ws_client.set_clock_sync(rest_conn.clock_sync());
+    /// ```
+    pub fn set_clock_sync(&mut self, clock: ClockSync) {
+        self.clock = clock;
     }
 
     /// 向websocket api连接发送请求，必须已经设置好了api_key(即必须通过`ws_api()`方法创建websocket连接)，否则将返回Error
@@ -701,12 +800,25 @@ impl WsClient {
         if self.api_sec_key.is_api_empty() {
             return Err(BiAnApiError::ApiKeyError);
         }
-        let param = PWebSocketApi::new(&self.api_sec_key, method, params);
+        let opts = SignOptions {
+            time_offset: self.clock.offset(),
+            clock: Some(self.clock.source().clone()),
+            ..Default::default()
+        };
+        let param = PWebSocketApi::new(
+            &self.api_sec_key,
+            self.signer.as_ref(),
+            method,
+            params,
+            &opts,
+        )
+        .await?;
         let msg_str = serde_json::to_string(&param).unwrap();
         let msg = Message::Text(msg_str.as_str().into());
         match self.ws.ws_writer.write().await.send(msg).await {
             Ok(_) => {
-                info!("msg send to websocket: {msg_str}")
+                // 请求中包含apiKey和signature，不记录请求内容
+                info!("msg send to websocket: {method}, id: {}", param.id)
             }
             Err(e) => {
                 error!("websocket connect closed: {}", e);
@@ -799,3 +911,30 @@ impl WsClient {
         self.send_api_req("klines", Some(&pkline)).await
     }
 }
+
+/// 响应是否为-1021错误(timestamp超出recvWindow)
+fn is_timestamp_error(text: &str) -> bool {
+    // 大部分响应不含错误，先检查字符串以避免额外的解析
+    if !text.contains("-1021") {
+        return false;
+    }
+    serde_json::from_str::<serde_json::Value>(text)
+        .ok()
+        .and_then(|v| v.get("error")?.get("code")?.as_i64())
+        == Some(-1021)
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    #[test]
+    fn test_is_timestamp_error() {
+        let err = r#"{"id":"1","status":400,"error":{"code":-1021,"msg":"Timestamp for this request is outside of the recvWindow."}}"#;
+        assert!(is_timestamp_error(err));
+        let err = r#"{"id":"1","status":400,"error":{"code":-1022,"msg":"Signature for this request is not valid."}}"#;
+        assert!(!is_timestamp_error(err));
+        let ok = r#"{"id":"-1021","status":200,"result":{}}"#;
+        assert!(!is_timestamp_error(ok));
+    }
+}
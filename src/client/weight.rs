@@ -0,0 +1,262 @@
+//! 各接口的权重
+//!
+//! 内置的权重表覆盖了本crate提供的所有接口，接口通过`Endpoint::weight_arg()`提供计算权重所依据的参数值
+//! (例如depth的limit、ticker的交易对个数)，由权重表中的规则换算为权重。
+//!
+//! 币安调整权重时，可以在运行时通过`RestConn::set_weight_table()`覆盖部分接口的规则，而无需升级本crate，
+//! 权重表的key为`<METHOD> <PATH>`：
+//!
+//! ```json
+//! {
+//!     "GET /api/v3/depth": { "weight": 250, "steps": [[100, 5], [500, 25], [1000, 50]] },
+//!     "GET /api/v3/trades": { "weight": 10 },
+//!     "POST /api/v3/order": { "weight": 1, "order": true }
+//! }
+//! ```
+
+use super::{RestMethod, rate_limit::RateLimitParam};
+use crate::errors::BiAnResult;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::LazyLock};
+
+/// 单个接口的权重规则
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeightRule {
+    /// 没有参数值，或者参数值超出了所有区间时的权重
+    pub weight: u32,
+    /// 按参数值分段的权重：(参数值的上限(包含), 权重)，按上限从小到大排列
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<(u32, u32)>,
+    /// 是否为下单请求，下单请求还会消耗下单次数
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub order: bool,
+}
+
+impl WeightRule {
+    pub fn new(weight: u32) -> Self {
+        Self {
+            weight,
+            steps: vec![],
+            order: false,
+        }
+    }
+
+    /// 按参数值分段的权重
+    pub fn steps(mut self, steps: impl IntoIterator<Item = (u32, u32)>) -> Self {
+        self.steps = steps.into_iter().collect();
+        self.steps.sort_by_key(|x| x.0);
+        self
+    }
+
+    /// 下单请求
+    pub fn order(mut self) -> Self {
+        self.order = true;
+        self
+    }
+
+    /// 根据参数值计算权重
+    pub fn weight(&self, arg: Option<u32>) -> RateLimitParam {
+        let weight = arg
+            .and_then(|n| self.steps.iter().find(|x| n <= x.0))
+            .map_or(self.weight, |x| x.1);
+        if self.order {
+            RateLimitParam::Order(weight)
+        } else {
+            RateLimitParam::Weight(weight)
+        }
+    }
+}
+
+/// 权重表，key为`<METHOD> <PATH>`，例如`GET /api/v3/depth`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct WeightTable {
+    rules: HashMap<String, WeightRule>,
+}
+
+/// 内置的权重表
+static BUILTIN: LazyLock<WeightTable> = LazyLock::new(|| {
+    use RestMethod::*;
+
+    let w = WeightRule::new;
+    let mut table = WeightTable::default();
+    for (method, path, rule) in [
+        (Get, "/api/v3/ping", w(1)),
+        (Get, "/api/v3/time", w(1)),
+        (Get, "/api/v3/exchangeInfo", w(20)),
+        // 参数值为limit
+        (
+            Get,
+            "/api/v3/depth",
+            w(250).steps([(100, 5), (500, 25), (1000, 50)]),
+        ),
+        // 币安文档中trades和historicalTrades的权重均为25，不随limit变化
+        (Get, "/api/v3/trades", w(25)),
+        (Get, "/api/v3/historicalTrades", w(25)),
+        (Get, "/api/v3/aggTrades", w(4)),
+        (Get, "/api/v3/klines", w(2)),
+        (Get, "/api/v3/avgPrice", w(2)),
+        // 参数值为交易对的个数，不指定交易对时为所有交易对
+        (Get, "/api/v3/ticker/price", w(4).steps([(1, 2)])),
+        (Get, "/api/v3/ticker/bookTicker", w(4).steps([(1, 2)])),
+        (
+            Get,
+            "/api/v3/ticker/24hr",
+            w(80).steps([(20, 2), (100, 40)]),
+        ),
+        (Post, "/api/v3/order", w(1).order()),
+        (Delete, "/api/v3/order", w(1)),
+        (Get, "/api/v3/order", w(4)),
+        (Delete, "/api/v3/openOrders", w(1)),
+        // 指定了交易对时参数值为1
+        (Get, "/api/v3/openOrders", w(80).steps([(1, 6)])),
+        (Get, "/api/v3/allOrders", w(20)),
+        (Get, "/api/v3/account", w(20)),
+        // 指定了orderId时参数值为1
+        (Get, "/api/v3/myTrades", w(20).steps([(1, 5)])),
+        (Get, "/api/v3/rateLimit/order", w(40)),
+        (Get, "/sapi/v1/spot/delist-schedule", w(100)),
+        // 币安文档中为IP权重10
+        (Get, "/sapi/v1/capital/config/getall", w(10)),
+        (Post, "/sapi/v1/asset/dust-btc", w(1)),
+        (Post, "/sapi/v1/asset/dust", w(10)),
+        (Get, "/sapi/v1/sub-account/list", w(1)),
+        (Get, "/sapi/v3/sub-account/assets", w(1)),
+        (Post, "/sapi/v1/sub-account/universalTransfer", w(1)),
+        (Get, "/sapi/v1/account/info", w(1)),
+    ] {
+        table.set(method, path, rule);
+    }
+    table
+});
+
+impl WeightTable {
+    /// 内置的权重表
+    pub fn builtin() -> &'static WeightTable {
+        &BUILTIN
+    }
+
+    /// 从JSON解析，格式参考模块文档
+    pub fn from_json(s: &str) -> BiAnResult<Self> {
+        let table: Self = serde_json::from_str(s)?;
+        Ok(table.normalize())
+    }
+
+    /// 从TOML解析，key需要加引号，例如`"GET /api/v3/trades" = { weight = 10 }`
+    #[cfg(feature = "toml")]
+    pub fn from_toml(s: &str) -> BiAnResult<Self> {
+        let table: Self = toml::from_str(s)
+            .map_err(|e| crate::errors::BiAnApiError::ArgumentError(e.to_string()))?;
+        Ok(table.normalize())
+    }
+
+    fn normalize(mut self) -> Self {
+        self.rules
+            .values_mut()
+            .for_each(|x| x.steps.sort_by_key(|x| x.0));
+        self
+    }
+
+    fn key(method: RestMethod, path: &str) -> String {
+        let method = match method {
+            RestMethod::Get => "GET",
+            RestMethod::Post => "POST",
+            RestMethod::Put => "PUT",
+            RestMethod::Delete => "DELETE",
+        };
+        format!("{method} {path}")
+    }
+
+    /// 设置接口的规则，已存在时替换
+    pub fn set(&mut self, method: RestMethod, path: &str, rule: WeightRule) {
+        self.rules.insert(Self::key(method, path), rule);
+    }
+
+    pub fn get(&self, method: RestMethod, path: &str) -> Option<&WeightRule> {
+        self.rules.get(&Self::key(method, path))
+    }
+
+    /// 接口的权重，表中没有该接口时返回None
+    pub fn weight(
+        &self,
+        method: RestMethod,
+        path: &str,
+        arg: Option<u32>,
+    ) -> Option<RateLimitParam> {
+        self.get(method, path).map(|x| x.weight(arg))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tt {
+    use super::*;
+
+    #[test]
+    fn test_weight_table() {
+        let table = WeightTable::from_json(
+            r#"{
+                "GET /api/v3/trades": { "weight": 10 },
+                "GET /api/v3/depth": { "weight": 100, "steps": [[1000, 20], [100, 2]] },
+                "POST /api/v3/order": { "weight": 2, "order": true }
+            }"#,
+        )
+        .unwrap();
+        let weight = |method, path, arg| match table.weight(method, path, arg) {
+            Some(RateLimitParam::Weight(n)) => Some((n, false)),
+            Some(RateLimitParam::Order(n)) => Some((n, true)),
+            _ => None,
+        };
+        assert_eq!(
+            weight(RestMethod::Get, "/api/v3/trades", None),
+            Some((10, false))
+        );
+        assert_eq!(
+            weight(RestMethod::Get, "/api/v3/depth", Some(100)),
+            Some((2, false))
+        );
+        assert_eq!(
+            weight(RestMethod::Get, "/api/v3/depth", Some(101)),
+            Some((20, false))
+        );
+        assert_eq!(
+            weight(RestMethod::Get, "/api/v3/depth", Some(5000)),
+            Some((100, false))
+        );
+        assert_eq!(
+            weight(RestMethod::Post, "/api/v3/order", None),
+            Some((2, true))
+        );
+        assert_eq!(weight(RestMethod::Delete, "/api/v3/order", None), None);
+
+        let builtin = WeightTable::builtin();
+        assert_eq!(
+            builtin.get(RestMethod::Get, "/api/v3/ticker/24hr"),
+            Some(&WeightRule::new(80).steps([(20, 2), (100, 40)]))
+        );
+        assert!(table.get(RestMethod::Get, "/api/v3/klines").is_none());
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn test_from_toml() {
+        let table = WeightTable::from_toml(
+            r#"
+            "GET /api/v3/trades" = { weight = 10 }
+            "GET /api/v3/depth" = { weight = 100, steps = [[1000, 20], [100, 2]] }
+            "#,
+        )
+        .unwrap();
+        let json = WeightTable::from_json(
+            r#"{
+                "GET /api/v3/trades": { "weight": 10 },
+                "GET /api/v3/depth": { "weight": 100, "steps": [[100, 2], [1000, 20]] }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(table, json);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::client::ResponseMeta;
 use std::{fmt::Display, io};
 use thiserror::Error;
 #[cfg(feature = "websocket")]
@@ -23,6 +24,10 @@ pub enum BiAnApiError {
     #[error("418 waf blocked")]
     Blocked,
 
+    /// 收到418或429后处于封禁状态，且设置了不等待封禁结束
+    #[error("banned until {until:?}")]
+    Banned { until: std::time::SystemTime },
+
     #[error("connect err to {0}")]
     ConnectError(String),
 
@@ -44,6 +49,14 @@ pub enum BiAnApiError {
     #[error("sec/private key missed or wrong")]
     SecKeyError,
 
+    /// 等待限速权重超时
+    #[error("rate limit wait timeout after {0:?}")]
+    RateLimitTimeout(std::time::Duration),
+
+    /// 签名失败，例如签名进程不可用
+    #[error("sign error: {0}")]
+    SignError(String),
+
     /// ws错误
     #[cfg(feature = "websocket")]
     #[error(transparent)]
@@ -59,6 +72,52 @@ pub enum BiAnApiError {
 
     #[error("unknown error: {0}")]
     Unknown(String),
+
+    /// 收到了响应但请求失败，附带响应的元信息(状态码、已用权重、请求ID等)，
+    /// 只由`rest_req_with_meta()`和`execute_with_meta()`返回，error为实际的错误
+    #[error("{error}")]
+    WithMeta {
+        error: Box<BiAnApiError>,
+        meta: Box<ResponseMeta>,
+    },
+}
+
+impl BiAnApiError {
+    /// 请求可能已经被服务器执行，但没有得到确定的结果(超时、5xx、-1006、-1007)，
+    /// 对于下单等非幂等的请求，需要查询后才能确定是否执行成功
+    pub fn is_unknown_status(&self) -> bool {
+        match self {
+            BiAnApiError::ServerError(_) => true,
+            BiAnApiError::BadRequest(code, _) => matches!(code, -1006 | -1007),
+            BiAnApiError::RequestError(e) => e.is_timeout(),
+            BiAnApiError::WithMeta { error, .. } => error.is_unknown_status(),
+            _ => false,
+        }
+    }
+
+    /// 附带响应的元信息
+    pub(crate) fn with_meta(self, meta: ResponseMeta) -> Self {
+        BiAnApiError::WithMeta {
+            error: Box::new(self.without_meta()),
+            meta: Box::new(meta),
+        }
+    }
+
+    /// 失败请求的响应的元信息，没有收到响应时为None
+    pub fn meta(&self) -> Option<&ResponseMeta> {
+        match self {
+            BiAnApiError::WithMeta { meta, .. } => Some(meta),
+            _ => None,
+        }
+    }
+
+    /// 去掉响应的元信息，返回实际的错误
+    pub fn without_meta(self) -> Self {
+        match self {
+            BiAnApiError::WithMeta { error, .. } => *error,
+            e => e,
+        }
+    }
 }
 
 pub type BiAnResult<T> = Result<T, BiAnApiError>;